@@ -0,0 +1,68 @@
+//! State cache throughput under synthetic workloads.
+//!
+//! Run with `cargo bench -p api --bench cache`. Each case replays a generated
+//! workload against a fresh `CacheLayer` and prints the read hit rate it reached
+//! alongside criterion's throughput numbers. Add a configuration to `CONFIGS` to
+//! have it benchmarked against every workload.
+
+use api::cache::{CacheConfig, CacheLayer};
+use api::cache_workload::{generate_workload, run_workload, KeyDistribution, WorkloadSpec};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+const NUM_OPS: usize = 10_000;
+
+/// Cache configurations under test, by name
+const CONFIGS: &[(&str, u64)] = &[("small", 64 * 1024), ("large", 4 * 1024 * 1024)];
+
+const DISTRIBUTIONS: &[(&str, KeyDistribution)] = &[
+    ("uniform", KeyDistribution::Uniform),
+    ("zipfian", KeyDistribution::Zipfian { exponent: 1.0 }),
+];
+
+const WRITE_RATIOS: &[f64] = &[0.05, 0.3];
+
+fn bench_workloads(c: &mut Criterion) {
+    let runtime = tokio::runtime::Runtime::new().expect("tokio runtime");
+    let mut group = c.benchmark_group("state_cache");
+    group.throughput(Throughput::Elements(NUM_OPS as u64));
+
+    for &(config_name, max_capacity) in CONFIGS {
+        for &(distribution_name, distribution) in DISTRIBUTIONS {
+            for &write_ratio in WRITE_RATIOS {
+                let ops = generate_workload(&WorkloadSpec {
+                    num_keys: 5_000,
+                    num_ops: NUM_OPS,
+                    write_ratio,
+                    distribution,
+                    seed: 42,
+                });
+                let config = CacheConfig {
+                    max_capacity,
+                    ..Default::default()
+                };
+                let id = format!(
+                    "{}/{}/writes={}",
+                    config_name, distribution_name, write_ratio
+                );
+
+                let hit_rate = runtime.block_on(run_workload(
+                    &CacheLayer::new(config.clone()),
+                    "bench",
+                    &ops,
+                ));
+                println!("{}: hit rate {:.1}%", id, hit_rate * 100.0);
+
+                group.bench_with_input(BenchmarkId::from_parameter(&id), &ops, |b, ops| {
+                    b.to_async(&runtime).iter(|| async {
+                        let cache = CacheLayer::new(config.clone());
+                        run_workload(&cache, "bench", ops).await
+                    });
+                });
+            }
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_workloads);
+criterion_main!(benches);
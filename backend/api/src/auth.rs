@@ -3,13 +3,16 @@ use ed25519_dalek::{Signature, Verifier, VerifyingKey};
 use jsonwebtoken::{decode, encode, Algorithm, DecodingKey, EncodingKey, Header, Validation};
 use rand::{distributions::Alphanumeric, Rng};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthClaims {
     pub sub: String,
     pub iat: i64,
     pub exp: i64,
+    /// Whether `sub` was listed in `ADMIN_ADDRESSES` when the token was issued
+    #[serde(default)]
+    pub admin: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -18,8 +21,12 @@ pub struct ChallengeRecord {
     pub expires_at: i64,
 }
 
+/// Placeholder secret older configs fell back to; never accepted from the environment
+const INSECURE_SECRET: &str = "dev-only-secret";
+
 pub struct AuthManager {
     challenges: HashMap<String, ChallengeRecord>,
+    admins: HashSet<String>,
     encoding_key: EncodingKey,
     decoding_key: DecodingKey,
 }
@@ -28,14 +35,32 @@ impl AuthManager {
     pub fn new(secret: String) -> Self {
         Self {
             challenges: HashMap::new(),
+            admins: HashSet::new(),
             encoding_key: EncodingKey::from_secret(secret.as_bytes()),
             decoding_key: DecodingKey::from_secret(secret.as_bytes()),
         }
     }
 
-    pub fn from_env() -> Self {
-        let secret = std::env::var("JWT_SECRET").unwrap_or_else(|_| "dev-only-secret".to_string());
-        Self::new(secret)
+    /// Reads the signing secret from `JWT_SECRET` and admin addresses from the
+    /// comma-separated `ADMIN_ADDRESSES`. Fails when no usable secret is set.
+    pub fn from_env() -> Result<Self, &'static str> {
+        let secret = std::env::var("JWT_SECRET")
+            .ok()
+            .filter(|secret| !secret.is_empty() && secret != INSECURE_SECRET)
+            .ok_or("jwt_secret_not_set")?;
+        let admins = std::env::var("ADMIN_ADDRESSES").unwrap_or_default();
+        let admins = admins
+            .split(',')
+            .map(str::trim)
+            .filter(|address| !address.is_empty())
+            .map(str::to_string);
+        Ok(Self::new(secret).with_admins(admins))
+    }
+
+    /// Addresses whose tokens carry the `admin` claim
+    pub fn with_admins(mut self, admins: impl IntoIterator<Item = String>) -> Self {
+        self.admins.extend(admins);
+        self
     }
 
     pub fn create_challenge(&mut self, address: &str) -> String {
@@ -83,6 +108,7 @@ impl AuthManager {
             sub: address.to_string(),
             iat,
             exp,
+            admin: self.admins.contains(address),
         };
         encode(&Header::default(), &claims, &self.encoding_key).map_err(|_| "jwt_encode_failed")
     }
@@ -117,7 +143,7 @@ fn decode_hex_64(value: &str) -> Option<[u8; 64]> {
 }
 
 fn decode_hex(value: &str) -> Option<Vec<u8>> {
-    if value.len() % 2 != 0 {
+    if !value.len().is_multiple_of(2) {
         return None;
     }
     (0..value.len())
@@ -166,4 +192,17 @@ mod tests {
         let second = auth.verify_and_issue_jwt(&vk_hex, &vk_hex, &sig_hex);
         assert!(second.is_err());
     }
+
+    #[test]
+    fn admin_claim_follows_admin_list() {
+        let sk = SigningKey::from_bytes(&[11u8; 32]);
+        let vk_hex = hex_encode(sk.verifying_key().as_bytes());
+        for admins in [vec![], vec![vk_hex.clone()]] {
+            let mut auth = AuthManager::new("test-secret".to_string()).with_admins(admins.clone());
+            let nonce = auth.create_challenge(&vk_hex);
+            let sig_hex = hex_encode(&sk.sign(nonce.as_bytes()).to_bytes());
+            let token = auth.verify_and_issue_jwt(&vk_hex, &vk_hex, &sig_hex).unwrap();
+            assert_eq!(auth.validate_jwt(&token).unwrap().admin, !admins.is_empty());
+        }
+    }
 }
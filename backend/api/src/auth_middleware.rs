@@ -11,6 +11,7 @@ use serde::Serialize;
 #[derive(Debug, Clone)]
 pub struct AuthContext {
     pub publisher_address: String,
+    pub admin: bool,
 }
 
 #[derive(Serialize)]
@@ -31,7 +32,9 @@ pub async fn auth_middleware(mut request: Request, next: Next) -> Response {
         return unauthorized("missing_bearer_token");
     };
 
-    let mgr = AuthManager::from_env();
+    let Ok(mgr) = AuthManager::from_env() else {
+        return unauthorized("auth_not_configured");
+    };
     let claims = match mgr.validate_jwt(token) {
         Ok(c) => c,
         Err(_) => return unauthorized("invalid_token"),
@@ -39,11 +42,32 @@ pub async fn auth_middleware(mut request: Request, next: Next) -> Response {
 
     request.extensions_mut().insert(AuthContext {
         publisher_address: claims.sub,
+        admin: claims.admin,
     });
 
     next.run(request).await
 }
 
+/// Admits only requests whose token carries the `admin` claim; layer it inside
+/// `auth_middleware`
+pub async fn admin_middleware(request: Request, next: Next) -> Response {
+    let admin = request
+        .extensions()
+        .get::<AuthContext>()
+        .is_some_and(|context| context.admin);
+    if !admin {
+        return (
+            StatusCode::FORBIDDEN,
+            Json(AuthErrorBody {
+                error: "Forbidden",
+                message: "admin_required",
+            }),
+        )
+            .into_response();
+    }
+    next.run(request).await
+}
+
 fn unauthorized(reason: &'static str) -> Response {
     (
         StatusCode::UNAUTHORIZED,
@@ -1,232 +1,6055 @@
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Duration;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use moka::future::Cache as MokaCache;
-use std::sync::Arc;
+use moka::notification::RemovalCause as MokaRemovalCause;
+use moka::ops::compute::{CompResult, Op};
+use moka::Expiry;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+use serde::{Deserialize, Serialize};
 use sqlx::PgPool;
+use thiserror::Error;
+use tokio::sync::{broadcast, mpsc, watch};
+use tokio_stream::wrappers::BroadcastStream;
+use tokio_stream::{Stream, StreamExt};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CacheConfigError {
+    #[error("Invalid cache capacity: max_capacity must be greater than 0")]
+    ZeroCapacity,
+    #[error("Invalid sweep interval: sweep_interval must be greater than 0")]
+    ZeroSweepInterval,
+    #[error("Invalid key separator: key_separator must not be empty")]
+    EmptyKeySeparator,
+    #[error("Invalid key separator: key_separator must not start with an ASCII digit")]
+    DigitLeadingKeySeparator,
+    #[error("Invalid TTL: global_ttl must be greater than 0")]
+    ZeroTtl,
+    #[error("Invalid TTL jitter: ttl_jitter ({jitter:?}) must be less than every configured TTL, including {ttl:?}")]
+    JitterExceedsTtl { jitter: Duration, ttl: Duration },
+    #[error("Invalid contract rule: ttl for contract {0} must be greater than 0")]
+    ZeroContractTtl(String),
+    #[error("Invalid circuit breaker: breaker_threshold must be greater than 0")]
+    ZeroBreakerThreshold,
+    #[error("Invalid hit rate floor: {0} is not between 0 and 1")]
+    InvalidHitRateFloor(f64),
+    #[error("Invalid latency EWMA alpha: {0} is not in (0, 1]")]
+    InvalidEwmaAlpha(f64),
+    #[error("Invalid event channel: event_channel_capacity must be greater than 0")]
+    ZeroEventCapacity,
+    #[error("Invalid load limit: max_concurrent_loads must be greater than 0")]
+    ZeroConcurrentLoads,
+    #[error("Invalid operation timeout: operation_timeout must be greater than 0")]
+    ZeroOperationTimeout,
+    #[error("Invalid auto-tune settings: {0}")]
+    InvalidAutoTune(&'static str),
+    #[error("Invalid config update: {0} cannot be changed at runtime")]
+    Immutable(&'static str),
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum CacheKeyError {
+    #[error("Invalid cache key: contract_id must not be empty")]
+    EmptyContractId,
+    #[error("Invalid cache key: key must not be empty")]
+    EmptyKey,
+}
 
 /// Cache configuration options
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
 pub struct CacheConfig {
     pub enabled: bool,
+    /// Weighted capacity of each cache, in bytes of key plus value. Moka fixes this
+    /// when a cache is built and has no in-place resize, so it cannot change for
+    /// the lifetime of a `CacheLayer`; a new capacity needs a new layer.
     pub max_capacity: u64,
+    /// Default TTL for the generic state cache when `put` is given no override
+    #[serde(rename = "global_ttl_secs", with = "duration_secs")]
+    pub global_ttl: Duration,
+    /// How long expired state entries are kept for `get_stale_while_revalidate`
+    #[serde(rename = "stale_window_secs", with = "duration_secs")]
+    pub stale_window: Duration,
+    /// Remaining lifetime below which `get_with_refresh` reloads an entry in the background
+    #[serde(rename = "refresh_ahead_secs", with = "duration_secs")]
+    pub refresh_ahead: Duration,
+    /// When set, each state entry's TTL is drawn uniformly from `ttl ± ttl_jitter`
+    /// so entries written together don't all expire together. The spread is capped
+    /// at half the entry's TTL, so a short TTL never jitters down to zero.
+    #[serde(rename = "ttl_jitter_secs", with = "option_duration_secs")]
+    pub ttl_jitter: Option<Duration>,
+    /// Seeds the jitter RNG so jittered TTLs repeat across runs; random when unset
+    pub ttl_jitter_seed: Option<u64>,
+    /// Default TTL for negative (known-absent) entries
+    #[serde(rename = "negative_ttl_secs", with = "duration_secs")]
+    pub negative_ttl: Duration,
+    /// How often the maintenance task purges expired entries
+    #[serde(rename = "sweep_interval_secs", with = "duration_secs")]
+    pub sweep_interval: Duration,
+    /// Codec applied to state cache values larger than `compression_threshold`
+    pub compression: Option<CompressionKind>,
+    /// Values up to this many bytes are always stored uncompressed
+    #[serde(rename = "compression_threshold_bytes")]
+    pub compression_threshold: usize,
+    /// Per-contract overrides, keyed by contract id (the state cache namespace)
+    pub per_contract: HashMap<String, ContractCacheRule>,
+    /// Consecutive loader failures within `breaker_window` that open the circuit
+    pub breaker_threshold: u32,
+    #[serde(rename = "breaker_window_secs", with = "duration_secs")]
+    pub breaker_window: Duration,
+    /// How long an open circuit rejects loads before letting one through again
+    #[serde(rename = "breaker_cooldown_secs", with = "duration_secs")]
+    pub breaker_cooldown: Duration,
+    /// Separator placed between the parts of a state cache key
+    pub key_separator: String,
+    /// Prepended to every state cache key, e.g. to keep environments apart
+    pub namespace_prefix: Option<String>,
+    /// Values longer than this many bytes are returned to callers but never cached
+    pub max_value_bytes: Option<usize>,
+    /// Keys longer than this many bytes are stored under a `#`-prefixed 128-bit
+    /// SHA-256 digest instead. Keys that start with `#` are hashed too, so no
+    /// literal key can equal a digest, and reads check the entry's original key.
+    pub hash_keys_longer_than: Option<usize>,
+    /// Restart an entry's TTL whenever `get` or `lookup` hits it
+    pub sliding_ttl: bool,
+    /// Record hits and misses as usual but answer every read as a miss: lookups return
+    /// nothing and loader paths call the loader, so the cache can be sized against
+    /// live traffic while callers always load fresh
+    pub shadow_mode: bool,
+    /// Warn when the hit rate over `hit_rate_window` drops below this fraction
+    pub hit_rate_floor: Option<f64>,
+    /// Window the `hit_rate_floor` check looks back over
+    #[serde(rename = "hit_rate_window_secs", with = "duration_secs")]
+    pub hit_rate_window: Duration,
+    /// Lookups the window needs before `hit_rate_floor` is enforced
+    pub hit_rate_min_lookups: usize,
+    /// Weight of each new sample in the moving latency averages, in (0, 1]; higher
+    /// reacts faster. Fixed per layer.
+    pub latency_ewma_alpha: f64,
+    /// URL that `invalidate_contract` POSTs an `InvalidationNotice` to
+    pub invalidation_webhook: Option<String>,
+    /// Most loader calls allowed in flight at once across all keys; fixed per layer
+    pub max_concurrent_loads: Option<usize>,
+    /// Time cache hits and loads for the latency metrics; hit/miss counts are kept either way
+    pub measure_latency: bool,
+    /// When a fallible load fails, serve the expired entry if `stale_window` still
+    /// retains one instead of returning the error
+    pub serve_stale_on_error: bool,
+    /// Store a CRC32 with each state value and snapshot entry and check it on read;
+    /// a mismatch is dropped and treated as a miss
+    pub verify_checksums: bool,
+    /// Buffer of the `event_stream` channel; `None` turns events off. A subscriber
+    /// that falls this far behind loses the oldest events. Fixed per layer.
+    pub event_channel_capacity: Option<usize>,
+    /// Upper bound on each state cache read, write or invalidation. A read that runs
+    /// over counts as a miss and a write that runs over is dropped; both are logged.
+    /// Loader calls are not bounded by it.
+    #[serde(rename = "operation_timeout_ms", with = "option_duration_millis")]
+    pub operation_timeout: Option<Duration>,
+    /// Experimental: let `spawn_auto_tune` move `global_ttl` towards a target hit rate
+    pub auto_tune: Option<AutoTuneConfig>,
+    /// Serve cached values but drop every write, e.g. to hold a known-good snapshot
+    /// through a maintenance window. Invalidation still works so stale state can be
+    /// removed, and loads on a miss still cache what they load. `sliding_ttl` hits
+    /// stop restarting TTLs.
+    pub frozen: bool,
+}
+
+/// Cache settings for a single contract that take precedence over the global ones
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContractCacheRule {
+    /// TTL for this contract's state entries when `put` is given no override
+    #[serde(rename = "ttl_secs", with = "option_duration_secs")]
+    pub ttl: Option<Duration>,
+}
+
+/// Bounds for `CacheLayer::spawn_auto_tune`. Each `adjust_interval` the hit rate
+/// since the last check is compared with `target_hit_rate`: well below it
+/// lengthens `global_ttl` unless the cache is already near `max_capacity`, well
+/// above it shortens `global_ttl` for fresher data. The TTL stays within
+/// `min_ttl..=max_ttl`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AutoTuneConfig {
+    pub target_hit_rate: f64,
+    #[serde(rename = "min_ttl_secs", with = "duration_secs")]
+    pub min_ttl: Duration,
+    #[serde(rename = "max_ttl_secs", with = "duration_secs")]
+    pub max_ttl: Duration,
+    #[serde(rename = "adjust_interval_secs", with = "duration_secs")]
+    pub adjust_interval: Duration,
+}
+
+impl Default for AutoTuneConfig {
+    fn default() -> Self {
+        Self {
+            target_hit_rate: 0.8,
+            min_ttl: Duration::from_secs(60),
+            max_ttl: Duration::from_secs(3600),
+            adjust_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Factor `global_ttl` is multiplied or divided by on each auto-tune adjustment
+const AUTO_TUNE_STEP: f64 = 1.25;
+
+/// How far the hit rate may drift from the target before the TTL is adjusted
+const AUTO_TUNE_TOLERANCE: f64 = 0.05;
+
+/// Share of `max_capacity` in use above which a longer TTL would only cause evictions
+const AUTO_TUNE_CAPACITY_HEADROOM: f64 = 0.9;
+
+/// Fluent builder for `CacheConfig`; unset fields keep their defaults
+#[derive(Clone, Debug, Default)]
+pub struct CacheConfigBuilder {
+    config: CacheConfig,
+}
+
+impl CacheConfigBuilder {
+    pub fn enabled(mut self, enabled: bool) -> Self {
+        self.config.enabled = enabled;
+        self
+    }
+
+    pub fn capacity(mut self, max_capacity: u64) -> Self {
+        self.config.max_capacity = max_capacity;
+        self
+    }
+
+    pub fn ttl(mut self, global_ttl: Duration) -> Self {
+        self.config.global_ttl = global_ttl;
+        self
+    }
+
+    pub fn stale_window(mut self, stale_window: Duration) -> Self {
+        self.config.stale_window = stale_window;
+        self
+    }
+
+    pub fn refresh_ahead(mut self, refresh_ahead: Duration) -> Self {
+        self.config.refresh_ahead = refresh_ahead;
+        self
+    }
+
+    pub fn ttl_jitter(mut self, ttl_jitter: Duration) -> Self {
+        self.config.ttl_jitter = Some(ttl_jitter);
+        self
+    }
+
+    pub fn ttl_jitter_seed(mut self, seed: u64) -> Self {
+        self.config.ttl_jitter_seed = Some(seed);
+        self
+    }
+
+    pub fn negative_ttl(mut self, negative_ttl: Duration) -> Self {
+        self.config.negative_ttl = negative_ttl;
+        self
+    }
+
+    pub fn sweep_interval(mut self, sweep_interval: Duration) -> Self {
+        self.config.sweep_interval = sweep_interval;
+        self
+    }
+
+    pub fn compression(mut self, compression: CompressionKind, threshold: usize) -> Self {
+        self.config.compression = Some(compression);
+        self.config.compression_threshold = threshold;
+        self
+    }
+
+    pub fn contract_rule(mut self, contract_id: impl Into<String>, rule: ContractCacheRule) -> Self {
+        self.config.per_contract.insert(contract_id.into(), rule);
+        self
+    }
+
+    pub fn circuit_breaker(mut self, threshold: u32, window: Duration, cooldown: Duration) -> Self {
+        self.config.breaker_threshold = threshold;
+        self.config.breaker_window = window;
+        self.config.breaker_cooldown = cooldown;
+        self
+    }
+
+    pub fn key_separator(mut self, key_separator: impl Into<String>) -> Self {
+        self.config.key_separator = key_separator.into();
+        self
+    }
+
+    pub fn namespace_prefix(mut self, namespace_prefix: impl Into<String>) -> Self {
+        self.config.namespace_prefix = Some(namespace_prefix.into());
+        self
+    }
+
+    pub fn max_value_bytes(mut self, max_value_bytes: usize) -> Self {
+        self.config.max_value_bytes = Some(max_value_bytes);
+        self
+    }
+
+    pub fn hash_keys_longer_than(mut self, hash_keys_longer_than: usize) -> Self {
+        self.config.hash_keys_longer_than = Some(hash_keys_longer_than);
+        self
+    }
+
+    pub fn sliding_ttl(mut self, sliding_ttl: bool) -> Self {
+        self.config.sliding_ttl = sliding_ttl;
+        self
+    }
+
+    pub fn shadow_mode(mut self, shadow_mode: bool) -> Self {
+        self.config.shadow_mode = shadow_mode;
+        self
+    }
+
+    pub fn measure_latency(mut self, measure_latency: bool) -> Self {
+        self.config.measure_latency = measure_latency;
+        self
+    }
+
+    pub fn auto_tune(mut self, auto_tune: AutoTuneConfig) -> Self {
+        self.config.auto_tune = Some(auto_tune);
+        self
+    }
+
+    pub fn event_channel_capacity(mut self, capacity: usize) -> Self {
+        self.config.event_channel_capacity = Some(capacity);
+        self
+    }
+
+    pub fn verify_checksums(mut self, verify_checksums: bool) -> Self {
+        self.config.verify_checksums = verify_checksums;
+        self
+    }
+
+    pub fn serve_stale_on_error(mut self, serve_stale_on_error: bool) -> Self {
+        self.config.serve_stale_on_error = serve_stale_on_error;
+        self
+    }
+
+    pub fn operation_timeout(mut self, operation_timeout: Duration) -> Self {
+        self.config.operation_timeout = Some(operation_timeout);
+        self
+    }
+
+    pub fn frozen(mut self, frozen: bool) -> Self {
+        self.config.frozen = frozen;
+        self
+    }
+
+    pub fn max_concurrent_loads(mut self, max_concurrent_loads: usize) -> Self {
+        self.config.max_concurrent_loads = Some(max_concurrent_loads);
+        self
+    }
+
+    pub fn invalidation_webhook(mut self, url: impl Into<String>) -> Self {
+        self.config.invalidation_webhook = Some(url.into());
+        self
+    }
+
+    pub fn hit_rate_floor(mut self, floor: f64, window: Duration, min_lookups: usize) -> Self {
+        self.config.hit_rate_floor = Some(floor);
+        self.config.hit_rate_window = window;
+        self.config.hit_rate_min_lookups = min_lookups;
+        self
+    }
+
+    pub fn latency_ewma_alpha(mut self, alpha: f64) -> Self {
+        self.config.latency_ewma_alpha = alpha;
+        self
+    }
+
+    pub fn build(self) -> CacheConfig {
+        self.config
+    }
+}
+
+/// (De)serializes a `Duration` as whole seconds. Deserialization also accepts a
+/// string with units, e.g. `"500ms"`, `"90s"` or `"1h30m"`, and serialization falls
+/// back to milliseconds when the duration is not a whole number of seconds.
+mod duration_secs {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    pub(super) enum DurationRepr {
+        Secs(u64),
+        Text(String),
+    }
+
+    impl DurationRepr {
+        pub(super) fn into_duration(self) -> Result<Duration, String> {
+            match self {
+                DurationRepr::Secs(secs) => Ok(Duration::from_secs(secs)),
+                DurationRepr::Text(text) => parse_duration(&text),
+            }
+        }
+    }
+
+    /// Parses a sequence of `<number><unit>` parts with units `ms`, `s`, `m`, `h`
+    /// and `d`, summing them
+    pub(super) fn parse_duration(text: &str) -> Result<Duration, String> {
+        let invalid = |reason: &str| format!("invalid duration {:?}: {}", text, reason);
+        let mut rest = text.trim();
+        if rest.is_empty() {
+            return Err(invalid("empty string"));
+        }
+        let mut total = Duration::ZERO;
+        while !rest.is_empty() {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            if digits == 0 {
+                return Err(invalid("expected a number"));
+            }
+            let value: u64 = rest[..digits].parse().map_err(|_| invalid("number too large"))?;
+            rest = &rest[digits..];
+            let unit_len = rest.find(|c: char| c.is_ascii_digit()).unwrap_or(rest.len());
+            let part = match &rest[..unit_len] {
+                "ms" => Some(Duration::from_millis(value)),
+                "s" => Some(Duration::from_secs(value)),
+                "m" => value.checked_mul(60).map(Duration::from_secs),
+                "h" => value.checked_mul(3600).map(Duration::from_secs),
+                "d" => value.checked_mul(86_400).map(Duration::from_secs),
+                "" => return Err(invalid("missing unit (use ms, s, m, h or d)")),
+                unit => return Err(invalid(&format!("unknown unit {:?}", unit))),
+            };
+            total = part
+                .and_then(|part| total.checked_add(part))
+                .ok_or_else(|| invalid("duration too large"))?;
+            rest = &rest[unit_len..];
+        }
+        Ok(total)
+    }
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        if duration.subsec_nanos() == 0 {
+            serializer.serialize_u64(duration.as_secs())
+        } else {
+            serializer.serialize_str(&format!("{}ms", duration.as_millis()))
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        DurationRepr::deserialize(deserializer)?.into_duration().map_err(D::Error::custom)
+    }
+}
+
+/// Optional counterpart of `duration_secs`
+mod option_duration_secs {
+    use super::duration_secs::DurationRepr;
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(d) => super::duration_secs::serialize(d, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Option::<DurationRepr>::deserialize(deserializer)?
+            .map(DurationRepr::into_duration)
+            .transpose()
+            .map_err(D::Error::custom)
+    }
+}
+
+/// Optional `Duration` as whole milliseconds, for `*_ms` fields. Deserialization
+/// also accepts a string with units like `duration_secs` does.
+mod option_duration_millis {
+    use super::duration_secs::{parse_duration, DurationRepr};
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error> {
+        match duration {
+            Some(d) => serializer.serialize_u64(d.as_millis().try_into().unwrap_or(u64::MAX)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+        Option::<DurationRepr>::deserialize(deserializer)?
+            .map(|repr| match repr {
+                DurationRepr::Secs(millis) => Ok(Duration::from_millis(millis)),
+                DurationRepr::Text(text) => parse_duration(&text),
+            })
+            .transpose()
+            .map_err(D::Error::custom)
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_capacity: 10_000,
+            global_ttl: Duration::from_secs(300),
+            stale_window: Duration::ZERO,
+            refresh_ahead: Duration::ZERO,
+            ttl_jitter: None,
+            ttl_jitter_seed: None,
+            negative_ttl: Duration::from_secs(60),
+            sweep_interval: Duration::from_secs(60),
+            compression: None,
+            compression_threshold: 4096,
+            per_contract: HashMap::new(),
+            breaker_threshold: 5,
+            breaker_window: Duration::from_secs(30),
+            breaker_cooldown: Duration::from_secs(30),
+            key_separator: ":".to_string(),
+            namespace_prefix: None,
+            max_value_bytes: None,
+            hash_keys_longer_than: None,
+            sliding_ttl: false,
+            shadow_mode: false,
+            hit_rate_floor: None,
+            hit_rate_window: Duration::from_secs(300),
+            hit_rate_min_lookups: 1000,
+            latency_ewma_alpha: DEFAULT_LATENCY_EWMA_ALPHA,
+            invalidation_webhook: None,
+            max_concurrent_loads: None,
+            measure_latency: true,
+            serve_stale_on_error: false,
+            verify_checksums: false,
+            event_channel_capacity: None,
+            operation_timeout: None,
+            auto_tune: None,
+            frozen: false,
+        }
+    }
 }
 
-impl Default for CacheConfig {
-    fn default() -> Self {
-        Self {
-            enabled: true,
-            max_capacity: 10_000,
+impl CacheConfig {
+    pub fn builder() -> CacheConfigBuilder {
+        CacheConfigBuilder::default()
+    }
+
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+
+        if let Ok(enabled_str) = std::env::var("CACHE_ENABLED") {
+            config.enabled = enabled_str.to_lowercase() == "true";
+        }
+
+        if let Ok(capacity_str) = std::env::var("CACHE_MAX_CAPACITY") {
+            if let Ok(capacity) = capacity_str.parse::<u64>() {
+                config.max_capacity = capacity;
+            } else {
+                // Support parsing like "10 GB" by just falling back to 10000 limit for elements if not
+            }
+        }
+
+        if let Ok(ttl_str) = std::env::var("CACHE_GLOBAL_TTL_SECS") {
+            if let Ok(secs) = ttl_str.parse::<u64>() {
+                config.global_ttl = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(window_str) = std::env::var("CACHE_STALE_WINDOW_SECS") {
+            if let Ok(secs) = window_str.parse::<u64>() {
+                config.stale_window = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(refresh_str) = std::env::var("CACHE_REFRESH_AHEAD_SECS") {
+            if let Ok(secs) = refresh_str.parse::<u64>() {
+                config.refresh_ahead = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(jitter_str) = std::env::var("CACHE_TTL_JITTER_SECS") {
+            if let Ok(secs) = jitter_str.parse::<u64>() {
+                config.ttl_jitter = Some(Duration::from_secs(secs));
+            }
+        }
+
+        if let Ok(ttl_str) = std::env::var("CACHE_NEGATIVE_TTL_SECS") {
+            if let Ok(secs) = ttl_str.parse::<u64>() {
+                config.negative_ttl = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(sweep_str) = std::env::var("CACHE_SWEEP_INTERVAL_SECS") {
+            if let Ok(secs) = sweep_str.parse::<u64>() {
+                config.sweep_interval = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(compression_str) = std::env::var("CACHE_COMPRESSION") {
+            match compression_str.to_lowercase().as_str() {
+                "lz4" => config.compression = Some(CompressionKind::Lz4),
+                "zstd" => config.compression = Some(CompressionKind::Zstd),
+                _ => {}
+            }
+        }
+
+        if let Ok(threshold_str) = std::env::var("CACHE_COMPRESSION_THRESHOLD_BYTES") {
+            if let Ok(threshold) = threshold_str.parse::<usize>() {
+                config.compression_threshold = threshold;
+            }
+        }
+
+        if let Ok(threshold_str) = std::env::var("CACHE_BREAKER_THRESHOLD") {
+            if let Ok(threshold) = threshold_str.parse::<u32>() {
+                config.breaker_threshold = threshold;
+            }
+        }
+
+        if let Ok(window_str) = std::env::var("CACHE_BREAKER_WINDOW_SECS") {
+            if let Ok(secs) = window_str.parse::<u64>() {
+                config.breaker_window = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(cooldown_str) = std::env::var("CACHE_BREAKER_COOLDOWN_SECS") {
+            if let Ok(secs) = cooldown_str.parse::<u64>() {
+                config.breaker_cooldown = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(separator) = std::env::var("CACHE_KEY_SEPARATOR") {
+            config.key_separator = separator;
+        }
+
+        if let Ok(prefix) = std::env::var("CACHE_NAMESPACE_PREFIX") {
+            if !prefix.is_empty() {
+                config.namespace_prefix = Some(prefix);
+            }
+        }
+
+        if let Ok(max_str) = std::env::var("CACHE_MAX_VALUE_BYTES") {
+            if let Ok(bytes) = max_str.parse::<usize>() {
+                config.max_value_bytes = Some(bytes);
+            }
+        }
+
+        if let Ok(max_str) = std::env::var("CACHE_HASH_KEYS_LONGER_THAN") {
+            if let Ok(bytes) = max_str.parse::<usize>() {
+                config.hash_keys_longer_than = Some(bytes);
+            }
+        }
+
+        if let Ok(sliding_str) = std::env::var("CACHE_SLIDING_TTL") {
+            config.sliding_ttl = sliding_str.to_lowercase() == "true";
+        }
+
+        if let Ok(shadow_str) = std::env::var("CACHE_SHADOW_MODE") {
+            config.shadow_mode = shadow_str.to_lowercase() == "true";
+        }
+
+        if let Ok(floor_str) = std::env::var("CACHE_HIT_RATE_FLOOR") {
+            if let Ok(floor) = floor_str.parse::<f64>() {
+                config.hit_rate_floor = Some(floor);
+            }
+        }
+
+        if let Ok(window_str) = std::env::var("CACHE_HIT_RATE_WINDOW_SECS") {
+            if let Ok(secs) = window_str.parse::<u64>() {
+                config.hit_rate_window = Duration::from_secs(secs);
+            }
+        }
+
+        if let Ok(min_str) = std::env::var("CACHE_HIT_RATE_MIN_LOOKUPS") {
+            if let Ok(min) = min_str.parse::<usize>() {
+                config.hit_rate_min_lookups = min;
+            }
+        }
+
+        if let Ok(alpha_str) = std::env::var("CACHE_LATENCY_EWMA_ALPHA") {
+            if let Ok(alpha) = alpha_str.parse::<f64>() {
+                config.latency_ewma_alpha = alpha;
+            }
+        }
+
+        if let Ok(measure_str) = std::env::var("CACHE_MEASURE_LATENCY") {
+            config.measure_latency = measure_str.to_lowercase() == "true";
+        }
+
+        if let Ok(target_str) = std::env::var("CACHE_AUTO_TUNE_TARGET_HIT_RATE") {
+            if let Ok(target) = target_str.parse::<f64>() {
+                let mut auto_tune = AutoTuneConfig {
+                    target_hit_rate: target,
+                    ..Default::default()
+                };
+                let secs = |name: &str| std::env::var(name).ok()?.parse::<u64>().ok();
+                if let Some(min) = secs("CACHE_AUTO_TUNE_MIN_TTL_SECS") {
+                    auto_tune.min_ttl = Duration::from_secs(min);
+                }
+                if let Some(max) = secs("CACHE_AUTO_TUNE_MAX_TTL_SECS") {
+                    auto_tune.max_ttl = Duration::from_secs(max);
+                }
+                if let Some(interval) = secs("CACHE_AUTO_TUNE_INTERVAL_SECS") {
+                    auto_tune.adjust_interval = Duration::from_secs(interval);
+                }
+                config.auto_tune = Some(auto_tune);
+            }
+        }
+
+        if let Ok(capacity_str) = std::env::var("CACHE_EVENT_CHANNEL_CAPACITY") {
+            if let Ok(capacity) = capacity_str.parse::<usize>() {
+                config.event_channel_capacity = Some(capacity);
+            }
+        }
+
+        if let Ok(verify_str) = std::env::var("CACHE_VERIFY_CHECKSUMS") {
+            config.verify_checksums = verify_str.to_lowercase() == "true";
+        }
+
+        if let Ok(stale_str) = std::env::var("CACHE_SERVE_STALE_ON_ERROR") {
+            config.serve_stale_on_error = stale_str.to_lowercase() == "true";
+        }
+
+        if let Ok(timeout_str) = std::env::var("CACHE_OPERATION_TIMEOUT_MS") {
+            if let Ok(ms) = timeout_str.parse::<u64>() {
+                config.operation_timeout = Some(Duration::from_millis(ms));
+            }
+        }
+
+        if let Ok(frozen_str) = std::env::var("CACHE_FROZEN") {
+            config.frozen = frozen_str.to_lowercase() == "true";
+        }
+
+        if let Ok(max_str) = std::env::var("CACHE_MAX_CONCURRENT_LOADS") {
+            if let Ok(max) = max_str.parse::<usize>() {
+                config.max_concurrent_loads = Some(max);
+            }
+        }
+
+        if let Ok(url) = std::env::var("CACHE_INVALIDATION_WEBHOOK_URL") {
+            if !url.is_empty() {
+                config.invalidation_webhook = Some(url);
+            }
+        }
+
+        tracing::info!(
+            "Cache config loaded: enabled={}, capacity={}, global_ttl={:?}",
+            config.enabled,
+            config.max_capacity,
+            config.global_ttl
+        );
+
+        config
+    }
+
+    /// TTL for a new state entry: the override, the contract's rule or `global_ttl`,
+    /// spread by `ttl_jitter`
+    fn entry_ttl(&self, ns: &str, ttl: Option<Duration>, rng: &Mutex<StdRng>) -> Duration {
+        let ttl = ttl
+            .or_else(|| self.per_contract.get(ns).and_then(|rule| rule.ttl))
+            .unwrap_or(self.global_ttl);
+        match self.ttl_jitter.map(|jitter| jitter.min(ttl / 2)) {
+            Some(jitter) if !jitter.is_zero() => {
+                let low = ttl.saturating_sub(jitter);
+                let high = ttl.saturating_add(jitter);
+                rng.lock().unwrap().gen_range(low..=high)
+            }
+            _ => ttl,
+        }
+    }
+
+    /// Source of `ttl_jitter`, seeded by `ttl_jitter_seed` when one is set
+    fn jitter_rng(&self) -> Mutex<StdRng> {
+        Mutex::new(match self.ttl_jitter_seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        })
+    }
+
+    /// Composes the internal state cache key as `[prefix sep] len sep ns sep key`.
+    /// The namespace is length-prefixed so that `("a:b", "c")` and `("a", "b:c")`
+    /// can never map to the same entry.
+    fn state_key(&self, ns: &str, key: &str) -> String {
+        let prefix = self.namespace_prefix.as_deref();
+        if self.is_hashed_key(key) {
+            let digest = Sha256::digest(key.as_bytes());
+            let hashed = format!("#{}", hex::encode(&digest[..16]));
+            compose_state_key(prefix, &self.key_separator, ns, &hashed)
+        } else {
+            compose_state_key(prefix, &self.key_separator, ns, key)
+        }
+    }
+
+    /// Rejects configurations that would leave a cache layer unusable
+    pub fn validate(&self) -> Result<(), CacheConfigError> {
+        if self.max_capacity == 0 {
+            return Err(CacheConfigError::ZeroCapacity);
+        }
+        if self.sweep_interval.is_zero() {
+            return Err(CacheConfigError::ZeroSweepInterval);
+        }
+        if self.key_separator.is_empty() {
+            return Err(CacheConfigError::EmptyKeySeparator);
+        }
+        // The key layout puts the namespace length right before a separator
+        if self.key_separator.starts_with(|c: char| c.is_ascii_digit()) {
+            return Err(CacheConfigError::DigitLeadingKeySeparator);
+        }
+        if self.global_ttl.is_zero() {
+            return Err(CacheConfigError::ZeroTtl);
+        }
+        if let Some(jitter) = self.ttl_jitter {
+            if jitter >= self.global_ttl {
+                return Err(CacheConfigError::JitterExceedsTtl { jitter, ttl: self.global_ttl });
+            }
+        }
+        if let Some((contract_id, _)) = self
+            .per_contract
+            .iter()
+            .find(|(_, rule)| rule.ttl.is_some_and(|ttl| ttl.is_zero()))
+        {
+            return Err(CacheConfigError::ZeroContractTtl(contract_id.clone()));
+        }
+        if let Some(jitter) = self.ttl_jitter {
+            let rule_ttls = self.per_contract.values().filter_map(|rule| rule.ttl);
+            if let Some(ttl) = rule_ttls.filter(|ttl| jitter >= *ttl).min() {
+                return Err(CacheConfigError::JitterExceedsTtl { jitter, ttl });
+            }
+        }
+        if self.breaker_threshold == 0 {
+            return Err(CacheConfigError::ZeroBreakerThreshold);
+        }
+        if self.max_concurrent_loads == Some(0) {
+            return Err(CacheConfigError::ZeroConcurrentLoads);
+        }
+        if self.operation_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            return Err(CacheConfigError::ZeroOperationTimeout);
+        }
+        if self.event_channel_capacity == Some(0) {
+            return Err(CacheConfigError::ZeroEventCapacity);
+        }
+        if let Some(floor) = self.hit_rate_floor {
+            if !(0.0..=1.0).contains(&floor) {
+                return Err(CacheConfigError::InvalidHitRateFloor(floor));
+            }
+        }
+        if !(self.latency_ewma_alpha > 0.0 && self.latency_ewma_alpha <= 1.0) {
+            return Err(CacheConfigError::InvalidEwmaAlpha(self.latency_ewma_alpha));
+        }
+        if let Some(tune) = &self.auto_tune {
+            if !(0.0..=1.0).contains(&tune.target_hit_rate) {
+                return Err(CacheConfigError::InvalidAutoTune("target_hit_rate must be between 0 and 1"));
+            }
+            if tune.min_ttl.is_zero() || tune.min_ttl > tune.max_ttl {
+                return Err(CacheConfigError::InvalidAutoTune("min_ttl must be positive and at most max_ttl"));
+            }
+            if tune.adjust_interval.is_zero() {
+                return Err(CacheConfigError::InvalidAutoTune("adjust_interval must be greater than 0"));
+            }
+            if let Some(jitter) = self.ttl_jitter.filter(|jitter| *jitter >= tune.min_ttl) {
+                return Err(CacheConfigError::JitterExceedsTtl { jitter, ttl: tune.min_ttl });
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether `state_key` stores `key` as a digest rather than verbatim. Keys that
+    /// look like a digest are hashed as well, so they can't land in a digest's slot.
+    fn is_hashed_key(&self, key: &str) -> bool {
+        self.hash_keys_longer_than.is_some_and(|max| key.len() > max || key.starts_with('#'))
+    }
+
+    /// Recovers the caller's key for a state entry, including hashed ones
+    fn entry_key<'a, V>(&self, cache_key: &'a str, entry: &'a StateEntry<V>) -> &'a str {
+        match &entry.original_key {
+            Some(key) => key,
+            None => self.key_in_ns(&entry.ns, cache_key),
+        }
+    }
+
+    /// Recovers the caller's key from a state cache key built by `state_key`
+    fn key_in_ns<'a>(&self, ns: &str, cache_key: &'a str) -> &'a str {
+        decompose_state_key(self.namespace_prefix.as_deref(), &self.key_separator, cache_key)
+            .filter(|(entry_ns, _)| *entry_ns == ns)
+            .map_or(cache_key, |(_, key)| key)
+    }
+}
+
+/// Builds `[prefix sep] len sep ns sep key`, the layout behind `CacheConfig::state_key`
+fn compose_state_key(prefix: Option<&str>, sep: &str, ns: &str, key: &str) -> String {
+    match prefix {
+        Some(prefix) => format!("{prefix}{sep}{}{sep}{ns}{sep}{key}", ns.len()),
+        None => format!("{}{sep}{ns}{sep}{key}", ns.len()),
+    }
+}
+
+/// Splits a key built by `compose_state_key` back into `(ns, key)`. Expects a
+/// separator that does not start with an ASCII digit, so the length ends where
+/// the separator begins.
+fn decompose_state_key<'a>(prefix: Option<&str>, sep: &str, cache_key: &'a str) -> Option<(&'a str, &'a str)> {
+    let rest = match prefix {
+        Some(prefix) => cache_key.strip_prefix(prefix)?.strip_prefix(sep)?,
+        None => cache_key,
+    };
+    let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+    let ns_len: usize = rest[..digits].parse().ok()?;
+    let rest = rest[digits..].strip_prefix(sep)?;
+    let ns = rest.get(..ns_len)?;
+    let key = rest[ns_len..].strip_prefix(sep)?;
+    Some((ns, key))
+}
+
+/// Per-layer cache counters, kept alongside the global Prometheus metrics
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    /// State cache lookups that found a live entry
+    pub hits: AtomicUsize,
+    /// State cache lookups that found nothing
+    pub misses: AtomicUsize,
+    /// Entries removed because of capacity pressure or TTL expiry
+    pub evictions: AtomicUsize,
+    /// Number of state cache hits that were timed
+    pub cached_count: AtomicUsize,
+    /// Total time spent serving those hits, in nanoseconds
+    pub cached_latency_ns: AtomicU64,
+    /// Number of loads that had to go to the backing source
+    pub uncached_count: AtomicUsize,
+    /// Total time spent in those loads, in nanoseconds
+    pub uncached_latency_ns: AtomicU64,
+    /// Fallible loads that returned an error
+    pub loader_errors: AtomicUsize,
+    /// Values not cached because they exceeded `max_value_bytes`
+    pub rejected_oversize: AtomicUsize,
+    /// Writes dropped because the cache was frozen
+    pub put_skipped_frozen: AtomicUsize,
+    /// Failed loads answered with an expired value under `serve_stale_on_error`
+    pub stale_served: AtomicUsize,
+    /// Entries dropped because their value no longer matched its checksum or decompressed
+    pub checksum_failures: AtomicUsize,
+    /// State cache operations abandoned after `operation_timeout`
+    pub timeouts: AtomicUsize,
+    cached_histogram: LatencyHistogram,
+    uncached_histogram: LatencyHistogram,
+    /// Periodic hit/miss readings backing `hit_rate_since`, oldest first
+    samples: Mutex<VecDeque<HitSample>>,
+    /// Moving latency averages in nanoseconds, stored as `f64` bits; 0 until the first sample
+    cached_ewma_ns: AtomicU64,
+    uncached_ewma_ns: AtomicU64,
+    /// `DEFAULT_LATENCY_EWMA_ALPHA` when unset
+    ewma_alpha: Option<f64>,
+}
+
+/// Hit and miss totals as of `at`
+#[derive(Clone, Copy, Debug)]
+struct HitSample {
+    at: Instant,
+    hits: usize,
+    misses: usize,
+}
+
+/// Most hit-rate samples kept; older ones are dropped first
+const MAX_HIT_SAMPLES: usize = 1024;
+
+/// Default `CacheConfig::latency_ewma_alpha`
+pub const DEFAULT_LATENCY_EWMA_ALPHA: f64 = 0.1;
+
+/// Folds `sample` into the moving average held in `ewma`
+fn update_ewma(ewma: &AtomicU64, alpha: f64, sample: Duration) {
+    let sample = sample.as_nanos() as f64;
+    let _ = ewma.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |bits| {
+        let next = if bits == 0 {
+            sample
+        } else {
+            let current = f64::from_bits(bits);
+            current + alpha * (sample - current)
+        };
+        Some(next.to_bits())
+    });
+}
+
+/// Window used for `CacheMetricsSnapshot::recent_hit_rate`
+pub const RECENT_HIT_RATE_WINDOW: Duration = Duration::from_secs(300);
+
+/// Storage engine behind every cache in this module
+pub const CACHE_BACKEND: &str = "moka";
+
+/// Moka's default policy, which none of the caches override: TinyLFU admission
+/// with LRU eviction
+pub const CACHE_EVICTION_POLICY: &str = "tiny-lfu";
+
+impl CacheMetrics {
+    /// Counters whose moving latency averages weight each new sample by `alpha`
+    pub fn with_ewma_alpha(alpha: f64) -> Self {
+        Self {
+            ewma_alpha: Some(alpha),
+            ..Default::default()
+        }
+    }
+
+    pub fn record_hit(&self) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_HITS.inc();
+    }
+
+    pub fn record_miss(&self) {
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_MISSES.inc();
+    }
+
+    pub fn record_loader_error(&self) {
+        self.loader_errors.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_LOADER_ERRORS.inc();
+    }
+
+    pub fn record_rejected_oversize(&self) {
+        self.rejected_oversize.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_REJECTED_OVERSIZE.inc();
+    }
+
+    pub fn record_checksum_failure(&self) {
+        self.checksum_failures.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_CHECKSUM_FAILURES.inc();
+    }
+
+    pub fn record_timeout(&self) {
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_TIMEOUTS.inc();
+    }
+
+    pub fn record_stale_served(&self) {
+        self.stale_served.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_STALE_SERVED.inc();
+    }
+
+    pub fn record_put_skipped_frozen(&self) {
+        self.put_skipped_frozen.fetch_add(1, Ordering::Relaxed);
+        crate::metrics::CACHE_PUT_SKIPPED_FROZEN.inc();
+    }
+
+    /// Fraction of state cache lookups that were hits (0.0 without lookups)
+    pub fn hit_rate(&self) -> f64 {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let total = hits + self.misses.load(Ordering::Relaxed);
+        if total == 0 {
+            return 0.0;
+        }
+        hits as f64 / total as f64
+    }
+
+    /// Records the current hit and miss totals for `hit_rate_since`
+    pub fn sample(&self) {
+        let sample = HitSample {
+            at: Instant::now(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        };
+        let mut samples = self.samples.lock().unwrap();
+        if samples.len() == MAX_HIT_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back(sample);
+    }
+
+    /// Hit rate over roughly the last `window`: lookups since the newest sample at
+    /// least that old, or since the oldest sample when none is. Useful for watching
+    /// the rate ramp up after a cold start. Falls back to `hit_rate` without samples.
+    pub fn hit_rate_since(&self, window: Duration) -> f64 {
+        let Some((hits, misses)) = self.lookups_since(window) else { return self.hit_rate() };
+        if hits + misses == 0 {
+            return 0.0;
+        }
+        hits as f64 / (hits + misses) as f64
+    }
+
+    /// Hits and misses since the baseline sample `hit_rate_since` would use
+    fn lookups_since(&self, window: Duration) -> Option<(usize, usize)> {
+        let baseline = {
+            let samples = self.samples.lock().unwrap();
+            samples
+                .iter()
+                .rev()
+                .find(|sample| sample.at.elapsed() >= window)
+                .or_else(|| samples.front())
+                .copied()
+        };
+        let baseline = baseline?;
+        let hits = self.hits.load(Ordering::Relaxed).saturating_sub(baseline.hits);
+        let misses = self.misses.load(Ordering::Relaxed).saturating_sub(baseline.misses);
+        Some((hits, misses))
+    }
+
+    /// Publishes the derived ratio and latency gauges to the Prometheus registry.
+    /// Hit/miss/eviction counters are incremented live and need no export.
+    pub fn export_prometheus(&self) {
+        crate::metrics::CACHE_HIT_RATIO.set(self.hit_rate());
+        crate::metrics::CACHE_AVG_CACHED_LATENCY_MICROS.set(self.avg_cached_latency());
+        crate::metrics::CACHE_AVG_UNCACHED_LATENCY_MICROS.set(self.avg_uncached_latency());
+    }
+
+    /// Reads every counter once and derives rates from those values, giving a
+    /// consistent point-in-time view suitable for returning from a handler
+    pub fn snapshot(&self) -> CacheMetricsSnapshot {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let cached_count = self.cached_count.load(Ordering::Relaxed);
+        let cached_latency_ns = self.cached_latency_ns.load(Ordering::Relaxed);
+        let uncached_count = self.uncached_count.load(Ordering::Relaxed);
+        let uncached_latency_ns = self.uncached_latency_ns.load(Ordering::Relaxed);
+
+        let lookups = hits + misses;
+        let hit_rate = if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 };
+        let avg = |total_ns: u64, count: usize| {
+            if count == 0 { 0.0 } else { total_ns as f64 / count as f64 / 1_000.0 }
+        };
+        let avg_cached_latency_us = avg(cached_latency_ns, cached_count);
+        let avg_uncached_latency_us = avg(uncached_latency_ns, uncached_count);
+        let improvement_factor =
+            if cached_count == 0 || uncached_count == 0 || avg_cached_latency_us <= 0.0 {
+                None
+            } else {
+                Some(avg_uncached_latency_us / avg_cached_latency_us)
+            };
+
+        CacheMetricsSnapshot {
+            backend: CACHE_BACKEND,
+            eviction_policy: CACHE_EVICTION_POLICY,
+            hits,
+            misses,
+            evictions: self.evictions.load(Ordering::Relaxed),
+            hit_rate,
+            cached_count,
+            uncached_count,
+            avg_cached_latency_us,
+            avg_uncached_latency_us,
+            improvement_factor,
+            loader_errors: self.loader_errors.load(Ordering::Relaxed),
+            rejected_oversize: self.rejected_oversize.load(Ordering::Relaxed),
+            put_skipped_frozen: self.put_skipped_frozen.load(Ordering::Relaxed),
+            stale_served: self.stale_served.load(Ordering::Relaxed),
+            timeouts: self.timeouts.load(Ordering::Relaxed),
+            checksum_failures: self.checksum_failures.load(Ordering::Relaxed),
+            ewma_cached_latency_us: self.ewma_cached_latency(),
+            ewma_uncached_latency_us: self.ewma_uncached_latency(),
+            recent_hit_rate: self.hit_rate_since(RECENT_HIT_RATE_WINDOW),
+        }
+    }
+
+    /// Zeroes every counter without touching cached data
+    pub fn reset(&self) {
+        self.hits.store(0, Ordering::Relaxed);
+        self.misses.store(0, Ordering::Relaxed);
+        self.evictions.store(0, Ordering::Relaxed);
+        self.cached_count.store(0, Ordering::Relaxed);
+        self.cached_latency_ns.store(0, Ordering::Relaxed);
+        self.uncached_count.store(0, Ordering::Relaxed);
+        self.uncached_latency_ns.store(0, Ordering::Relaxed);
+        self.loader_errors.store(0, Ordering::Relaxed);
+        self.rejected_oversize.store(0, Ordering::Relaxed);
+        self.put_skipped_frozen.store(0, Ordering::Relaxed);
+        self.stale_served.store(0, Ordering::Relaxed);
+        self.timeouts.store(0, Ordering::Relaxed);
+        self.checksum_failures.store(0, Ordering::Relaxed);
+        self.samples.lock().unwrap().clear();
+        self.cached_ewma_ns.store(0, Ordering::Relaxed);
+        self.uncached_ewma_ns.store(0, Ordering::Relaxed);
+        self.cached_histogram.reset();
+        self.uncached_histogram.reset();
+    }
+
+    pub fn eviction_count(&self) -> usize {
+        self.evictions.load(Ordering::Relaxed)
+    }
+
+    pub fn record_cached_latency(&self, latency: Duration) {
+        self.cached_count.fetch_add(1, Ordering::Relaxed);
+        self.cached_latency_ns
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+        self.cached_histogram.record(latency);
+        update_ewma(&self.cached_ewma_ns, self.ewma_alpha(), latency);
+    }
+
+    pub fn record_uncached_latency(&self, latency: Duration) {
+        self.uncached_count.fetch_add(1, Ordering::Relaxed);
+        self.uncached_latency_ns
+            .fetch_add(latency.as_nanos() as u64, Ordering::Relaxed);
+        self.uncached_histogram.record(latency);
+        update_ewma(&self.uncached_ewma_ns, self.ewma_alpha(), latency);
+    }
+
+    /// Times a cache hit from `start`; `None` means latency measurement is off
+    pub fn record_cached_since(&self, start: Option<Instant>) {
+        if let Some(start) = start {
+            self.record_cached_latency(start.elapsed());
+        }
+    }
+
+    /// Times a backing-source load from `start`; `None` means latency measurement is off
+    pub fn record_uncached_since(&self, start: Option<Instant>) {
+        if let Some(start) = start {
+            self.record_uncached_latency(start.elapsed());
+        }
+    }
+
+    /// Mean latency of timed cache hits in microseconds (0.0 without samples)
+    pub fn avg_cached_latency(&self) -> f64 {
+        average_micros(&self.cached_latency_ns, &self.cached_count)
+    }
+
+    /// Mean latency of backing-source loads in microseconds (0.0 without samples)
+    pub fn avg_uncached_latency(&self) -> f64 {
+        average_micros(&self.uncached_latency_ns, &self.uncached_count)
+    }
+
+    /// Moving average of cache hit latency in microseconds, weighted towards recent
+    /// hits so it tracks changes the cumulative mean would smooth over (0.0 without samples)
+    pub fn ewma_cached_latency(&self) -> f64 {
+        f64::from_bits(self.cached_ewma_ns.load(Ordering::Relaxed)) / 1_000.0
+    }
+
+    /// Moving average of load latency in microseconds (0.0 without samples)
+    pub fn ewma_uncached_latency(&self) -> f64 {
+        f64::from_bits(self.uncached_ewma_ns.load(Ordering::Relaxed)) / 1_000.0
+    }
+
+    fn ewma_alpha(&self) -> f64 {
+        self.ewma_alpha.unwrap_or(DEFAULT_LATENCY_EWMA_ALPHA)
+    }
+
+    /// How many times faster a cache hit is than a load, or `None` until both
+    /// hits and loads have been measured
+    pub fn improvement_factor(&self) -> Option<f64> {
+        if self.cached_count.load(Ordering::Relaxed) == 0
+            || self.uncached_count.load(Ordering::Relaxed) == 0
+        {
+            return None;
+        }
+        let cached = self.avg_cached_latency();
+        if cached <= 0.0 {
+            return None;
+        }
+        Some(self.avg_uncached_latency() / cached)
+    }
+
+    /// Latency percentiles in microseconds, accurate to within a factor of two
+    pub fn cached_latency_p50(&self) -> f64 {
+        self.cached_histogram.percentile(0.50)
+    }
+
+    pub fn cached_latency_p95(&self) -> f64 {
+        self.cached_histogram.percentile(0.95)
+    }
+
+    pub fn cached_latency_p99(&self) -> f64 {
+        self.cached_histogram.percentile(0.99)
+    }
+
+    pub fn uncached_latency_p50(&self) -> f64 {
+        self.uncached_histogram.percentile(0.50)
+    }
+
+    pub fn uncached_latency_p95(&self) -> f64 {
+        self.uncached_histogram.percentile(0.95)
+    }
+
+    pub fn uncached_latency_p99(&self) -> f64 {
+        self.uncached_histogram.percentile(0.99)
+    }
+}
+
+/// Serializable point-in-time copy of `CacheMetrics`
+#[derive(Clone, Debug, Serialize)]
+pub struct CacheMetricsSnapshot {
+    /// `CACHE_BACKEND`, so a snapshot read on its own says what it measured
+    pub backend: &'static str,
+    pub eviction_policy: &'static str,
+    pub hits: usize,
+    pub misses: usize,
+    pub evictions: usize,
+    pub hit_rate: f64,
+    pub cached_count: usize,
+    pub uncached_count: usize,
+    pub avg_cached_latency_us: f64,
+    pub avg_uncached_latency_us: f64,
+    pub improvement_factor: Option<f64>,
+    pub loader_errors: usize,
+    pub rejected_oversize: usize,
+    pub put_skipped_frozen: usize,
+    pub stale_served: usize,
+    pub checksum_failures: usize,
+    pub timeouts: usize,
+    /// Moving averages weighted towards recent samples; see `latency_ewma_alpha`
+    pub ewma_cached_latency_us: f64,
+    pub ewma_uncached_latency_us: f64,
+    /// Hit rate over the last `RECENT_HIT_RATE_WINDOW`
+    pub recent_hit_rate: f64,
+}
+
+/// Age of the state cache's entries, measured from when each was last written
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AgeStats {
+    pub oldest: Duration,
+    pub newest: Duration,
+    pub count: usize,
+}
+
+/// A cached state value with its freshness, as returned by `CacheLayer::get_with_meta`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CacheEntryMeta {
+    pub value: String,
+    /// Time since the value was written
+    pub age: Duration,
+    pub ttl_remaining: Duration,
+    /// Ledger sequence the value was read at, if it was stored with one
+    pub ledger_seq: Option<u32>,
+}
+
+/// Outcome of `CacheLayer::get_detailed`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheLookup {
+    /// A fresh value from the state cache
+    Hit(String),
+    /// A fresh negative entry: the key is known to be absent upstream
+    NegativeHit,
+    /// Nothing fresh is cached for the key
+    Miss,
+}
+
+/// Overall verdict of `CacheLayer::health`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheHealthStatus {
+    Ok,
+    Degraded,
+}
+
+/// Result of `CacheLayer::health`, reported as the `cache` component of `/health`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CacheHealth {
+    pub status: CacheHealthStatus,
+    pub enabled: bool,
+    /// Always true for the in-process Moka backend
+    pub backend_reachable: bool,
+    /// A maintenance or invalidation task panicked and is no longer running
+    pub background_task_failed: bool,
+}
+
+/// State of the circuit breaker guarding fallible loads
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Loads run normally
+    Closed,
+    /// Too many recent failures; loads are rejected until the cooldown ends
+    Open,
+    /// The cooldown ended; the next load decides whether the circuit closes again
+    HalfOpen,
+}
+
+/// Failure bookkeeping for `CacheLayer::try_get_or_load`
+#[derive(Debug, Default)]
+struct CircuitBreaker {
+    consecutive_failures: u32,
+    window_start: Option<Instant>,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    fn state(&self) -> CircuitState {
+        match self.open_until {
+            None => CircuitState::Closed,
+            Some(until) if Instant::now() < until => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    fn record_success(&mut self) {
+        *self = Self::default();
+    }
+
+    fn record_failure(&mut self, config: &CacheConfig) {
+        let now = Instant::now();
+        if self.window_start.is_none_or(|start| now.duration_since(start) > config.breaker_window) {
+            self.consecutive_failures = 0;
+            self.window_start = Some(now);
+        }
+        self.consecutive_failures += 1;
+        // A failed trial while half-open counts too, so the circuit reopens right away
+        if self.consecutive_failures >= config.breaker_threshold || self.open_until.is_some() {
+            self.open_until = Some(now + config.breaker_cooldown);
+        }
+    }
+}
+
+/// Why `CacheLayer::try_get_or_load` produced no value
+#[derive(Debug, Error)]
+pub enum LoadError<E> {
+    #[error("loader circuit is open")]
+    CircuitOpen,
+    #[error("loader failed: {0}")]
+    Loader(Arc<E>),
+}
+
+/// Why a coalesced load left nothing in the cache. An oversized value still
+/// reaches every waiter; it just isn't stored.
+enum UncachedLoad<E> {
+    Loader(Arc<E>),
+    Oversize(String),
+}
+
+#[derive(Debug, Default)]
+struct ContractCounters {
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+impl ContractCounters {
+    fn snapshot(&self) -> ContractMetrics {
+        let hits = self.hits.load(Ordering::Relaxed);
+        let misses = self.misses.load(Ordering::Relaxed);
+        let lookups = hits + misses;
+        let hit_rate = if lookups == 0 { 0.0 } else { hits as f64 / lookups as f64 };
+        ContractMetrics { hits, misses, hit_rate }
+    }
+}
+
+/// Hit/miss breakdown for a single contract's state cache lookups
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ContractMetrics {
+    pub hits: usize,
+    pub misses: usize,
+    pub hit_rate: f64,
+}
+
+/// Lock-free latency histogram with power-of-two nanosecond buckets.
+/// Bucket `i` holds samples in `[2^(i-1), 2^i)` ns; bucket 0 holds zero.
+#[derive(Debug)]
+struct LatencyHistogram {
+    buckets: [AtomicU64; 65],
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self {
+            buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+        }
+    }
+}
+
+impl LatencyHistogram {
+    fn record(&self, latency: Duration) {
+        let nanos = latency.as_nanos().min(u64::MAX as u128) as u64;
+        let bucket = (u64::BITS - nanos.leading_zeros()) as usize;
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Upper bound of the bucket containing quantile `q`, in microseconds (0.0 if empty)
+    fn percentile(&self, q: f64) -> f64 {
+        let counts: Vec<u64> = self.buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect();
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return 0.0;
+        }
+        let target = ((total as f64 * q).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in counts.iter().enumerate() {
+            seen += count;
+            if seen >= target {
+                return 2f64.powi(bucket as i32) / 1_000.0;
+            }
+        }
+        2f64.powi(64) / 1_000.0
+    }
+}
+
+fn average_micros(total_ns: &AtomicU64, count: &AtomicUsize) -> f64 {
+    let count = count.load(Ordering::Relaxed);
+    if count == 0 {
+        return 0.0;
+    }
+    total_ns.load(Ordering::Relaxed) as f64 / count as f64 / 1_000.0
+}
+
+/// Counts size and expiry removals; explicit invalidations and replacements are ignored
+fn eviction_listener<V>(
+    metrics: Arc<CacheMetrics>,
+) -> impl Fn(Arc<String>, V, MokaRemovalCause) + Send + Sync + 'static {
+    move |_key, _value, cause| {
+        if matches!(cause, MokaRemovalCause::Size | MokaRemovalCause::Expired) {
+            metrics.evictions.fetch_add(1, Ordering::Relaxed);
+            crate::metrics::CACHE_EVICTIONS.inc();
+        }
+    }
+}
+
+/// An HTTP response held by the response cache: the status, headers and body
+/// replayed to later requests for the same URL
+#[derive(Clone, Debug)]
+pub struct CachedResponse {
+    pub status: axum::http::StatusCode,
+    pub headers: axum::http::HeaderMap,
+    pub body: axum::body::Bytes,
+}
+
+/// Codec used for large state cache values
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CompressionKind {
+    Lz4,
+    Zstd,
+}
+
+/// State cache payload; large values may be held compressed and are inflated on read
+#[derive(Clone, Debug)]
+pub enum CachedValue {
+    Plain(String),
+    Compressed(CompressionKind, Arc<[u8]>),
+}
+
+impl CachedValue {
+    /// Compresses `value` when the config enables it, the value exceeds the threshold
+    /// and compression actually saves space
+    fn encode(value: String, config: &CacheConfig) -> Self {
+        let kind = match config.compression {
+            Some(kind) if value.len() > config.compression_threshold => kind,
+            _ => return Self::Plain(value),
+        };
+        let compressed = match kind {
+            CompressionKind::Lz4 => Some(lz4_flex::compress_prepend_size(value.as_bytes())),
+            CompressionKind::Zstd => zstd::bulk::compress(value.as_bytes(), 0).ok(),
+        };
+        match compressed {
+            Some(bytes) if bytes.len() < value.len() => Self::Compressed(kind, bytes.into()),
+            _ => Self::Plain(value),
+        }
+    }
+
+    /// The original string, or `None` if the stored bytes no longer decompress
+    fn decode(self) -> Option<String> {
+        let bytes = match self {
+            Self::Plain(value) => return Some(value),
+            Self::Compressed(CompressionKind::Lz4, bytes) => {
+                lz4_flex::decompress_size_prepended(&bytes).ok()?
+            }
+            Self::Compressed(CompressionKind::Zstd, bytes) => zstd::stream::decode_all(&bytes[..]).ok()?,
+        };
+        String::from_utf8(bytes).ok()
+    }
+
+    /// CRC32 of the stored bytes, compressed or not
+    fn checksum(&self) -> u32 {
+        match self {
+            Self::Plain(value) => crc32fast::hash(value.as_bytes()),
+            Self::Compressed(_, bytes) => crc32fast::hash(bytes),
+        }
+    }
+
+    /// Bytes held in memory for the value, as counted against `max_capacity`
+    fn stored_len(&self) -> usize {
+        match self {
+            Self::Plain(value) => value.len(),
+            Self::Compressed(_, bytes) => bytes.len(),
+        }
+    }
+}
+
+/// Why an entry left the state cache, as reported to `CacheLayer::on_removal` listeners
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RemovalCause {
+    /// The entry's TTL ran out
+    Expired,
+    /// The entry was evicted to stay within `max_capacity`
+    Size,
+    /// The entry was invalidated or cleared
+    Explicit,
+    /// The entry was overwritten by a newer `put`
+    Replaced,
+}
+
+impl From<MokaRemovalCause> for RemovalCause {
+    fn from(cause: MokaRemovalCause) -> Self {
+        match cause {
+            MokaRemovalCause::Expired => Self::Expired,
+            MokaRemovalCause::Size => Self::Size,
+            MokaRemovalCause::Explicit => Self::Explicit,
+            MokaRemovalCause::Replaced => Self::Replaced,
+        }
+    }
+}
+
+type RemovalListener = Arc<dyn Fn(String, String, RemovalCause) + Send + Sync>;
+
+/// State cache activity published on `CacheLayer::event_stream`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CacheEvent {
+    Hit { contract_id: String, key: String },
+    Miss { contract_id: String, key: String },
+    /// A value written by one of the `put` methods
+    Put { contract_id: String, key: String },
+    /// `key` is `None` when `invalidate_contract` dropped the whole contract
+    Invalidate { contract_id: String, key: Option<String> },
+    /// An entry that expired or was evicted for space
+    Evict { contract_id: String, key: String, cause: RemovalCause },
+}
+
+/// Sends the event built by `event` when anyone is listening; without subscribers
+/// nothing is allocated
+fn emit_event(events: &Option<broadcast::Sender<CacheEvent>>, event: impl FnOnce() -> CacheEvent) {
+    if let Some(tx) = events {
+        if tx.receiver_count() > 0 {
+            let _ = tx.send(event());
+        }
+    }
+}
+
+/// Value stored in the generic state cache along with the TTL it was written with
+#[derive(Clone, Debug)]
+pub struct StateEntry<V = CachedValue> {
+    ns: String,
+    value: V,
+    ttl: Duration,
+    /// How long the entry is retained past `ttl` for stale-while-revalidate reads
+    stale_for: Duration,
+    written_at: Instant,
+    /// Ledger sequence the value was read at, if known
+    ledger_seq: Option<u32>,
+    /// The caller's key, kept only when the cache key holds a digest of it
+    original_key: Option<Arc<str>>,
+    /// CRC32 of the value when `verify_checksums` is on
+    checksum: Option<u32>,
+}
+
+impl<V> StateEntry<V> {
+    fn new(ns: &str, value: V, ttl: Duration) -> Self {
+        Self {
+            ns: ns.to_string(),
+            value,
+            ttl,
+            stale_for: Duration::ZERO,
+            written_at: Instant::now(),
+            ledger_seq: None,
+            original_key: None,
+            checksum: None,
+        }
+    }
+
+    /// Whether this entry was written for `key`, which only differs from its slot
+    /// for hashed keys
+    fn is_for_key(&self, config: &CacheConfig, key: &str) -> bool {
+        !config.is_hashed_key(key) || self.original_key.as_deref() == Some(key)
+    }
+
+    fn with_original_key(mut self, config: &CacheConfig, key: &str) -> Self {
+        if config.is_hashed_key(key) {
+            self.original_key = Some(key.into());
+        }
+        self
+    }
+
+    fn with_stale_window(mut self, stale_for: Duration) -> Self {
+        self.stale_for = stale_for;
+        self
+    }
+
+    fn with_ledger_seq(mut self, ledger_seq: Option<u32>) -> Self {
+        self.ledger_seq = ledger_seq;
+        self
+    }
+
+    fn is_fresh(&self) -> bool {
+        self.written_at.elapsed() < self.ttl
+    }
+}
+
+impl StateEntry {
+    fn with_checksum(mut self, config: &CacheConfig) -> Self {
+        if config.verify_checksums {
+            self.checksum = Some(self.value.checksum());
+        }
+        self
+    }
+
+    /// Whether the value still matches its checksum; entries without one always pass
+    fn checksum_ok(&self) -> bool {
+        self.checksum.is_none_or(|checksum| checksum == self.value.checksum())
+    }
+}
+
+/// Per-entry expiry so `put` can override the global TTL for individual keys
+struct StateExpiry;
+
+impl<V> Expiry<String, StateEntry<V>> for StateExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &String,
+        entry: &StateEntry<V>,
+        _created_at: Instant,
+    ) -> Option<Duration> {
+        Some(entry.ttl.saturating_add(entry.stale_for))
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &StateEntry<V>,
+        _updated_at: Instant,
+        _duration_until_expiry: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl.saturating_add(entry.stale_for))
+    }
+}
+
+/// Most contracts with per-contract counters at once. Lookups for further contracts
+/// are not broken down until the maintenance sweep drops counters of contracts
+/// with nothing cached.
+const MAX_TRACKED_CONTRACTS: usize = 10_000;
+
+/// Maximum number of loader calls `CacheLayer::warm` runs at once
+const WARM_CONCURRENCY: usize = 16;
+
+/// Delivery attempts for one invalidation webhook before giving up
+const WEBHOOK_MAX_ATTEMPTS: u32 = 4;
+
+/// Wait before the first webhook retry; doubles after each failed attempt
+const WEBHOOK_BASE_BACKOFF: Duration = Duration::from_millis(200);
+
+/// Body POSTed to `CacheConfig::invalidation_webhook`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct InvalidationNotice {
+    pub contract_id: String,
+    pub reason: String,
+    /// Unix time of the invalidation, in milliseconds
+    pub ts: u64,
+}
+
+/// Delivers `notice` to `url`, retrying with exponential backoff. Failures are
+/// only logged; the invalidation has already happened.
+async fn send_invalidation_webhook(client: reqwest::Client, url: String, notice: InvalidationNotice) {
+    let mut backoff = WEBHOOK_BASE_BACKOFF;
+    for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+        let error = match client.post(&url).json(&notice).send().await {
+            Ok(response) if response.status().is_success() => return,
+            Ok(response) => format!("HTTP {}", response.status()),
+            Err(e) => e.to_string(),
+        };
+        if attempt == WEBHOOK_MAX_ATTEMPTS {
+            tracing::warn!(
+                contract_id = %notice.contract_id,
+                attempts = attempt,
+                "Cache invalidation webhook failed: {}",
+                error
+            );
+            return;
+        }
+        tracing::debug!(attempt, "Cache invalidation webhook failed, retrying: {}", error);
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+}
+
+/// Outcome of `CacheLayer::warm`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct WarmReport {
+    pub warmed: usize,
+    pub failed: usize,
+}
+
+impl WarmReport {
+    fn record(&mut self, result: Option<Result<bool, tokio::task::JoinError>>) {
+        match result {
+            Some(Ok(true)) => self.warmed += 1,
+            Some(_) => self.failed += 1,
+            None => {}
+        }
+    }
+}
+
+/// On-disk format written by `CacheLayer::save_snapshot`
+#[derive(Debug, Serialize, Deserialize)]
+struct CacheSnapshot {
+    saved_at_ms: u64,
+    entries: Vec<SnapshotEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SnapshotEntry {
+    ns: String,
+    key: String,
+    value: String,
+    ttl_remaining_ms: u64,
+    ledger_seq: Option<u32>,
+    /// CRC32 of `value`, written when `verify_checksums` is on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    checksum: Option<u32>,
+}
+
+/// The state key a cached response was served for, from a URL like
+/// `/api/contracts/{id}/state/{key}?...`
+fn state_path_key(url: &str) -> Option<&str> {
+    let path = url.split('?').next().unwrap_or(url);
+    let (resource, key) = path.rsplit_once('/')?;
+    resource.ends_with("/state").then_some(key)
+}
+
+fn unix_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Signals that a contract's on-chain state changed, e.g. from an ingested contract event
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidationEvent {
+    pub contract_id: String,
+}
+
+/// Broadcast channel carrying `InvalidationEvent`s from event ingestion to cache layers
+#[derive(Clone, Debug)]
+pub struct InvalidationBus {
+    tx: broadcast::Sender<InvalidationEvent>,
+}
+
+impl InvalidationBus {
+    /// `capacity` is how many events a slow subscriber may fall behind before it lags
+    pub fn new(capacity: usize) -> Self {
+        let (tx, _) = broadcast::channel(capacity);
+        Self { tx }
+    }
+
+    /// Publishes an event, returning how many subscribers will receive it
+    pub fn publish(&self, contract_id: impl Into<String>) -> usize {
+        self.tx
+            .send(InvalidationEvent { contract_id: contract_id.into() })
+            .unwrap_or(0)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<InvalidationEvent> {
+        self.tx.subscribe()
+    }
+}
+
+/// A state cache key: the contract it belongs to plus the key within that
+/// contract. Built once with `from_parts` so the two parts cannot be swapped
+/// at a call site.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    contract_id: String,
+    key: String,
+}
+
+impl CacheKey {
+    pub fn from_parts(
+        contract_id: impl Into<String>,
+        key: impl Into<String>,
+    ) -> Result<Self, CacheKeyError> {
+        let (contract_id, key) = (contract_id.into(), key.into());
+        if contract_id.is_empty() {
+            return Err(CacheKeyError::EmptyContractId);
+        }
+        if key.is_empty() {
+            return Err(CacheKeyError::EmptyKey);
+        }
+        Ok(Self { contract_id, key })
+    }
+
+    pub fn contract_id(&self) -> &str {
+        &self.contract_id
+    }
+
+    pub fn key(&self) -> &str {
+        &self.key
+    }
+}
+
+impl std::fmt::Display for CacheKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.contract_id, self.key)
+    }
+}
+
+/// The cache subsystem. Every piece of state lives behind an `Arc` (Moka caches
+/// share their storage when cloned), so cloning a layer is cheap and all clones
+/// see the same entries, config and metrics.
+#[derive(Clone)]
+pub struct CacheLayer {
+    pub abi_cache: MokaCache<String, String>,
+    pub verification_cache: MokaCache<String, String>,
+    pub state_cache: MokaCache<String, StateEntry>,
+    /// Keys known to be absent upstream, so lookups can skip the loader
+    pub negative_cache: MokaCache<String, StateEntry<()>>,
+    /// HTTP responses cached by `CacheMiddleware`, kept apart from `state_cache` so
+    /// values written with `put` can never be served as a response
+    pub response_cache: MokaCache<String, StateEntry<CachedResponse>>,
+    /// Keys with a stale-while-revalidate refresh in flight
+    refreshing: Arc<Mutex<HashSet<String>>>,
+    /// Key and `written_at` of entries being rewritten by `touch_entry`, so the
+    /// replacement is not reported to removal listeners
+    touching: Arc<Mutex<HashSet<(String, Instant)>>>,
+    removal_listeners: Arc<RwLock<Vec<RemovalListener>>>,
+    metrics: Arc<CacheMetrics>,
+    /// HTTP client for the invalidation webhook, built on first use
+    webhook_client: Arc<OnceLock<reqwest::Client>>,
+    /// Per-contract hit/miss counters, keyed by state cache namespace
+    contract_counters: Arc<RwLock<HashMap<String, ContractCounters>>>,
+    /// State cache keys written under each tag by `put_tagged`
+    tags: Arc<RwLock<HashMap<String, HashSet<String>>>>,
+    config: Arc<RwLock<Arc<CacheConfig>>>,
+    breaker: Arc<Mutex<CircuitBreaker>>,
+    /// Caps concurrent loader calls when `max_concurrent_loads` is set
+    load_permits: Option<Arc<tokio::sync::Semaphore>>,
+    /// Draws TTL jitter; seeded from `ttl_jitter_seed` when set
+    jitter_rng: Arc<Mutex<StdRng>>,
+    /// Flipped to `true` by `shutdown` to stop background tasks
+    shutdown_tx: Arc<watch::Sender<bool>>,
+    /// Each background task holds a clone; `shutdown` waits for all of them to drop
+    task_guard: Arc<Mutex<Option<mpsc::Sender<()>>>>,
+    tasks_done: Arc<tokio::sync::Mutex<mpsc::Receiver<()>>>,
+    /// Set when a background task panics
+    task_panicked: Arc<AtomicBool>,
+    /// Publishes `CacheEvent`s when `event_channel_capacity` is set
+    events: Option<broadcast::Sender<CacheEvent>>,
+    /// Alternate contract ids, mapped to the canonical id entries are stored under
+    aliases: Arc<RwLock<HashMap<String, String>>>,
+}
+
+/// Wraps a background task so a panic sets `panicked` before it propagates
+struct PanicWatch<F> {
+    task: Pin<Box<F>>,
+    panicked: Arc<AtomicBool>,
+}
+
+impl<F: Future<Output = ()>> Future for PanicWatch<F> {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<()> {
+        let this = &mut *self;
+        match std::panic::catch_unwind(AssertUnwindSafe(|| this.task.as_mut().poll(cx))) {
+            Ok(poll) => poll,
+            Err(panic) => {
+                this.panicked.store(true, Ordering::Relaxed);
+                std::panic::resume_unwind(panic)
+            }
+        }
+    }
+}
+
+impl CacheLayer {
+    /// Builds the cache layer, rejecting configurations that would leave it unusable
+    pub fn try_new(config: CacheConfig) -> Result<Self, CacheConfigError> {
+        config.validate()?;
+        Ok(Self::new(config))
+    }
+
+    pub fn new(config: CacheConfig) -> Self {
+        let metrics = Arc::new(CacheMetrics::with_ewma_alpha(config.latency_ewma_alpha));
+
+        // 24-hour TTL for ABI, max size configurable default 10GB but we use the config max_capacity 
+        let abi_cache = MokaCache::builder()
+            .max_capacity(config.max_capacity)
+            .weigher(|k: &String, v: &String| -> u32 {
+                (k.len() + v.len()).try_into().unwrap_or(u32::MAX)
+            })
+            .time_to_live(Duration::from_secs(24 * 3600))
+            .eviction_listener(eviction_listener(metrics.clone()))
+            .build();
+
+        // 7-day TTL for verification result cache, keyed by bytecode_hash
+        let verification_cache = MokaCache::builder()
+            .max_capacity(config.max_capacity)
+            .weigher(|k: &String, v: &String| -> u32 {
+                (k.len() + v.len()).try_into().unwrap_or(u32::MAX)
+            })
+            .time_to_live(Duration::from_secs(7 * 24 * 3600))
+            .eviction_listener(eviction_listener(metrics.clone()))
+            .build();
+
+        // Namespaced state cache; TTL is decided per entry (global_ttl unless overridden)
+        let removal_listeners: Arc<RwLock<Vec<RemovalListener>>> = Arc::default();
+        let count_eviction = eviction_listener(metrics.clone());
+        let listeners = removal_listeners.clone();
+        let touching: Arc<Mutex<HashSet<(String, Instant)>>> = Arc::default();
+        let touched = touching.clone();
+        let key_config = config.clone();
+        let events = config.event_channel_capacity.map(|capacity| broadcast::channel(capacity).0);
+        let evict_events = events.clone();
+        let state_cache = MokaCache::builder()
+            .max_capacity(config.max_capacity)
+            .weigher(|k: &String, v: &StateEntry| -> u32 {
+                let original_key = v.original_key.as_ref().map_or(0, |key| key.len());
+                (k.len() + original_key + v.value.stored_len()).try_into().unwrap_or(u32::MAX)
+            })
+            .expire_after(StateExpiry)
+            .eviction_listener(move |k: Arc<String>, v: StateEntry, cause| {
+                if cause == MokaRemovalCause::Replaced {
+                    let mut touched = touched.lock().unwrap();
+                    if !touched.is_empty() && touched.remove(&(k.to_string(), v.written_at)) {
+                        return;
+                    }
+                }
+                let listeners = listeners.read().unwrap();
+                if !listeners.is_empty() {
+                    let key = key_config.entry_key(&k, &v);
+                    for listener in listeners.iter() {
+                        listener(v.ns.clone(), key.to_string(), cause.into());
+                    }
+                }
+                if matches!(cause, MokaRemovalCause::Size | MokaRemovalCause::Expired) {
+                    emit_event(&evict_events, || CacheEvent::Evict {
+                        contract_id: v.ns.clone(),
+                        key: key_config.entry_key(&k, &v).to_string(),
+                        cause: cause.into(),
+                    });
+                }
+                count_eviction(k, v, cause);
+            })
+            .build();
+
+        let negative_cache = MokaCache::builder()
+            .max_capacity(config.max_capacity)
+            .weigher(|k: &String, _v: &StateEntry<()>| -> u32 {
+                k.len().try_into().unwrap_or(u32::MAX)
+            })
+            .expire_after(StateExpiry)
+            .eviction_listener(eviction_listener(metrics.clone()))
+            .build();
+
+        let response_cache = MokaCache::builder()
+            .max_capacity(config.max_capacity)
+            .weigher(|k: &String, v: &StateEntry<CachedResponse>| -> u32 {
+                let headers: usize = v
+                    .value
+                    .headers
+                    .iter()
+                    .map(|(name, value)| name.as_str().len() + value.len())
+                    .sum();
+                (k.len() + headers + v.value.body.len()).try_into().unwrap_or(u32::MAX)
+            })
+            .expire_after(StateExpiry)
+            .eviction_listener(eviction_listener(metrics.clone()))
+            .build();
+
+        let (shutdown_tx, _) = watch::channel(false);
+        let (task_guard, tasks_done) = mpsc::channel(1);
+
+        Self {
+            abi_cache,
+            verification_cache,
+            state_cache,
+            negative_cache,
+            response_cache,
+            refreshing: Arc::default(),
+            touching,
+            removal_listeners,
+            metrics,
+            contract_counters: Arc::default(),
+            tags: Arc::default(),
+            webhook_client: Arc::default(),
+            breaker: Arc::default(),
+            load_permits: config
+                .max_concurrent_loads
+                .map(|permits| Arc::new(tokio::sync::Semaphore::new(permits))),
+            jitter_rng: Arc::new(config.jitter_rng()),
+            shutdown_tx: Arc::new(shutdown_tx),
+            task_guard: Arc::new(Mutex::new(Some(task_guard))),
+            tasks_done: Arc::new(tokio::sync::Mutex::new(tasks_done)),
+            task_panicked: Arc::new(AtomicBool::new(false)),
+            events,
+            aliases: Arc::default(),
+            config: Arc::new(RwLock::new(Arc::new(config))),
+        }
+    }
+
+    /// Storage engine behind the layer, for diagnostics such as `/cache/stats`
+    pub fn backend_name(&self) -> &'static str {
+        CACHE_BACKEND
+    }
+
+    /// How the backend picks entries to admit and evict once `max_capacity` is reached
+    pub fn eviction_policy(&self) -> &'static str {
+        CACHE_EVICTION_POLICY
+    }
+
+    /// Live feed of state cache activity. Empty unless `event_channel_capacity` is
+    /// set; a subscriber that lags loses the oldest events rather than slowing the cache.
+    pub fn event_stream(&self) -> impl Stream<Item = CacheEvent> {
+        let rx = match &self.events {
+            Some(tx) => tx.subscribe(),
+            // The sender is dropped at once, so this stream ends immediately
+            None => broadcast::channel(1).1,
+        };
+        BroadcastStream::new(rx).filter_map(|event| event.ok())
+    }
+
+    /// Makes `alias` another name for the `canonical` contract id: reads, writes and
+    /// invalidations under either name share the same entries. Anything cached under
+    /// `alias` before it was registered is dropped so no second copy lingers. Aliases
+    /// that pointed at `alias` are moved to `canonical`, so every alias resolves in
+    /// one step.
+    pub async fn register_alias(&self, alias: impl Into<String>, canonical: impl Into<String>) {
+        let alias = alias.into();
+        let canonical = self.canonical_ns(&canonical.into()).into_owned();
+        if alias == canonical { return; }
+        self.remove_contract_entries(&alias).await;
+        let mut aliases = self.aliases.write().unwrap();
+        for target in aliases.values_mut().filter(|target| **target == alias) {
+            target.clone_from(&canonical);
+        }
+        aliases.insert(alias, canonical);
+    }
+
+    /// The canonical contract id for `ns`, which is `ns` itself unless it is an alias
+    fn canonical_ns<'a>(&self, ns: &'a str) -> std::borrow::Cow<'a, str> {
+        match self.aliases.read().unwrap().get(ns) {
+            Some(canonical) => std::borrow::Cow::Owned(canonical.clone()),
+            None => std::borrow::Cow::Borrowed(ns),
+        }
+    }
+
+    /// Current configuration; a snapshot, so later `update_config` calls don't affect it
+    pub fn config(&self) -> Arc<CacheConfig> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Swaps in a new configuration without dropping warm entries. TTL settings
+    /// apply to entries written afterwards. Disabling the cache also clears the
+    /// state and response caches, since writes and invalidations are skipped
+    /// while disabled. Settings baked into the caches at construction can't
+    /// change and are rejected.
+    pub async fn update_config(&self, new: CacheConfig) -> Result<(), CacheConfigError> {
+        new.validate()?;
+        let current = self.config();
+        if new.max_capacity != current.max_capacity {
+            return Err(CacheConfigError::Immutable("max_capacity"));
+        }
+        if new.sweep_interval != current.sweep_interval {
+            return Err(CacheConfigError::Immutable("sweep_interval"));
+        }
+        if new.max_concurrent_loads != current.max_concurrent_loads {
+            return Err(CacheConfigError::Immutable("max_concurrent_loads"));
+        }
+        if new.event_channel_capacity != current.event_channel_capacity {
+            return Err(CacheConfigError::Immutable("event_channel_capacity"));
+        }
+        if new.latency_ewma_alpha != current.latency_ewma_alpha {
+            return Err(CacheConfigError::Immutable("latency_ewma_alpha"));
+        }
+        if new.key_separator != current.key_separator
+            || new.namespace_prefix != current.namespace_prefix
+            || new.hash_keys_longer_than != current.hash_keys_longer_than
+        {
+            return Err(CacheConfigError::Immutable("state key format"));
+        }
+
+        let disabling = current.enabled && !new.enabled;
+        *self.config.write().unwrap() = Arc::new(new);
+        if disabling {
+            self.state_cache.invalidate_all();
+            self.negative_cache.invalidate_all();
+            self.response_cache.invalidate_all();
+        }
+        tracing::info!("Cache config updated: {:?}", self.config());
+        Ok(())
+    }
+
+    pub fn metrics(&self) -> &CacheMetrics {
+        &self.metrics
+    }
+
+    /// Registers a callback invoked with `(contract_id, key, cause)` whenever a state
+    /// entry leaves the cache. Listeners run on the thread performing the removal.
+    pub fn on_removal<F>(&self, listener: F)
+    where
+        F: Fn(String, String, RemovalCause) + Send + Sync + 'static,
+    {
+        self.removal_listeners.write().unwrap().push(Arc::new(listener));
+    }
+
+    /// Starts a fresh metrics window while keeping warm entries
+    pub fn reset_metrics(&self) {
+        self.metrics.reset();
+        self.contract_counters.write().unwrap().clear();
+    }
+
+    /// Hit/miss counts for a single contract's state lookups, if it has had any. The
+    /// maintenance sweep drops the counts of contracts with nothing cached.
+    pub fn contract_metrics(&self, contract_id: &str) -> Option<ContractMetrics> {
+        let contract_id = self.canonical_ns(contract_id);
+        self.contract_counters.read().unwrap().get(contract_id.as_ref()).map(ContractCounters::snapshot)
+    }
+
+    /// The `n` contracts with the most state cache misses, most first
+    pub fn top_contracts_by_misses(&self, n: usize) -> Vec<(String, ContractMetrics)> {
+        let mut contracts: Vec<(String, ContractMetrics)> = self
+            .contract_counters
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(contract_id, counters)| (contract_id.clone(), counters.snapshot()))
+            .collect();
+        contracts.sort_by(|a, b| b.1.misses.cmp(&a.1.misses).then_with(|| a.0.cmp(&b.0)));
+        contracts.truncate(n);
+        contracts
+    }
+
+    fn record_hit(&self, ns: &str, key: &str) {
+        self.metrics.record_hit();
+        emit_event(&self.events, || CacheEvent::Hit {
+            contract_id: ns.to_string(),
+            key: key.to_string(),
+        });
+        self.with_contract_counters(ns, |c| {
+            c.hits.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    fn record_miss(&self, ns: &str, key: &str) {
+        self.metrics.record_miss();
+        emit_event(&self.events, || CacheEvent::Miss {
+            contract_id: ns.to_string(),
+            key: key.to_string(),
+        });
+        self.with_contract_counters(ns, |c| {
+            c.misses.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+
+    fn with_contract_counters(&self, ns: &str, f: impl FnOnce(&ContractCounters)) {
+        if let Some(counters) = self.contract_counters.read().unwrap().get(ns) {
+            f(counters);
+            return;
+        }
+        let mut counters = self.contract_counters.write().unwrap();
+        if counters.len() >= MAX_TRACKED_CONTRACTS && !counters.contains_key(ns) { return; }
+        f(counters.entry(ns.to_string()).or_default());
+    }
+
+    /// Drops the per-contract counters of contracts with nothing cached, so lookups
+    /// for ids that never resolve don't pile up
+    fn prune_contract_counters(&self) {
+        let cached: HashSet<String> = self
+            .state_cache
+            .iter()
+            .map(|(_, entry)| entry.ns.clone())
+            .chain(self.negative_cache.iter().map(|(_, entry)| entry.ns.clone()))
+            .chain(self.response_cache.iter().map(|(_, entry)| entry.ns.clone()))
+            .collect();
+        self.contract_counters.write().unwrap().retain(|ns, _| cached.contains(ns));
+    }
+
+    /// Number of entries across all caches (eventually consistent with recent writes)
+    pub fn entry_count(&self) -> u64 {
+        self.abi_cache.entry_count()
+            + self.verification_cache.entry_count()
+            + self.state_cache.entry_count()
+            + self.negative_cache.entry_count()
+            + self.response_cache.entry_count()
+    }
+
+    /// Approximate memory held by cached keys and values, in bytes
+    pub fn estimated_size_bytes(&self) -> u64 {
+        self.abi_cache.weighted_size()
+            + self.verification_cache.weighted_size()
+            + self.state_cache.weighted_size()
+            + self.negative_cache.weighted_size()
+            + self.response_cache.weighted_size()
+    }
+
+    pub async fn get_abi(&self, contract_id: &str) -> Option<String> {
+        if !self.config().enabled { return None; }
+        let result = self.abi_cache.get(contract_id).await;
+        if result.is_some() {
+            crate::metrics::ABI_CACHE_HITS.inc();
+        } else {
+            crate::metrics::ABI_CACHE_MISSES.inc();
+        }
+        result
+    }
+
+    pub async fn put_abi(&self, contract_id: &str, abi: String) {
+        if !self.config().enabled { return; }
+        self.abi_cache.insert(contract_id.to_string(), abi).await;
+    }
+
+    pub async fn invalidate_abi(&self, contract_id: &str) {
+        if !self.config().enabled { return; }
+        self.abi_cache.invalidate(contract_id).await;
+    }
+
+    pub async fn get_verification(&self, bytecode_hash: &str) -> Option<String> {
+        if !self.config().enabled { return None; }
+        let result = self.verification_cache.get(bytecode_hash).await;
+        if result.is_some() {
+            crate::metrics::VERIFICATION_CACHE_HITS.inc();
+        } else {
+            crate::metrics::VERIFICATION_CACHE_MISSES.inc();
+        }
+        result
+    }
+
+    pub async fn put_verification(&self, bytecode_hash: &str, result: String) {
+        if !self.config().enabled { return; }
+        self.verification_cache.insert(bytecode_hash.to_string(), result).await;
+    }
+
+    pub async fn invalidate_verification(&self, bytecode_hash: &str) {
+        if !self.config().enabled { return; }
+        self.verification_cache.invalidate(bytecode_hash).await;
+    }
+
+    /// Looks up `key` under namespace `ns`, returning the value and whether it was a hit.
+    /// A negative entry is reported as `(None, true)`.
+    /// Traced at debug level with the contract id only; keys and values stay out of spans.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(contract_id = %ns, cache.backend = CACHE_BACKEND, cache.hit = tracing::field::Empty)
+    )]
+    pub async fn get(&self, ns: &str, key: &str) -> (Option<String>, bool) {
+        let (value, hit) = match self.lookup(ns, key).await {
+            Some(value) => (value, true),
+            None => (None, false),
+        };
+        tracing::Span::current().record("cache.hit", hit);
+        tracing::debug!("state cache lookup");
+        (value, hit)
+    }
+
+    /// Like `get`, but also reports how old the value is and how long it has left,
+    /// e.g. for `Age` or `ETag` headers. Counts as a hit or miss like `get`.
+    pub async fn get_with_meta(&self, ns: &str, key: &str) -> Option<CacheEntryMeta> {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return None; }
+        let cache_key = self.config().state_key(ns, key);
+        let Some(entry) = self.read_entry(&cache_key, key).await.filter(|e| e.is_fresh()) else {
+            self.record_miss(ns, key);
+            return None;
+        };
+        let age = entry.written_at.elapsed();
+        let (ttl_remaining, ledger_seq) = (entry.ttl.saturating_sub(age), entry.ledger_seq);
+        let Some(value) = self.decode_entry(&cache_key, entry).await else {
+            self.record_miss(ns, key);
+            return None;
+        };
+        self.record_hit(ns, key);
+        if self.config().shadow_mode { return None; }
+        Some(CacheEntryMeta { ttl_remaining, age, ledger_seq, value })
+    }
+
+    /// Returns the cached value for `ns`/`key`, or `default` without caching it
+    pub async fn get_or_default(&self, ns: &str, key: &str, default: String) -> String {
+        self.get(ns, key).await.0.unwrap_or(default)
+    }
+
+    /// Looks up several keys of one contract; results are in `keys` order and
+    /// each key counts as its own hit or miss
+    pub async fn get_many(&self, ns: &str, keys: &[&str]) -> Vec<Option<String>> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.lookup(ns, key).await.flatten());
+        }
+        values
+    }
+
+    /// Like `get`, but tells a cached absence (`Some(None)`) apart from a key
+    /// that is not cached at all (`None`)
+    pub async fn lookup(&self, ns: &str, key: &str) -> Option<Option<String>> {
+        match self.get_detailed(ns, key).await {
+            CacheLookup::Hit(value) => Some(Some(value)),
+            CacheLookup::NegativeHit => Some(None),
+            CacheLookup::Miss => None,
+        }
+    }
+
+    /// Looks up `ns`/`key` and reports where the answer came from. Negative hits
+    /// count as hits in the metrics.
+    pub async fn get_detailed(&self, ns: &str, key: &str) -> CacheLookup {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return CacheLookup::Miss; }
+        let start = self.start_timer();
+        let cache_key = self.config().state_key(ns, key);
+        let entry = if self.config().sliding_ttl {
+            self.touch_entry(cache_key.clone(), key).await
+        } else {
+            self.read_entry(&cache_key, key).await.filter(|e| e.is_fresh())
+        };
+        let value = match entry {
+            Some(entry) => self.decode_entry(&cache_key, entry).await,
+            None => None,
+        };
+        let result = match value {
+            Some(value) => CacheLookup::Hit(value),
+            None => match self.bounded("get", self.negative_cache.get(&cache_key)).await.flatten() {
+                Some(negative) if negative.is_fresh() => CacheLookup::NegativeHit,
+                _ => CacheLookup::Miss,
+            },
+        };
+        if result != CacheLookup::Miss {
+            self.metrics.record_cached_since(start);
+            self.record_hit(ns, key);
+        } else {
+            self.record_miss(ns, key);
+        }
+        if self.config().shadow_mode {
+            return CacheLookup::Miss;
+        }
+        result
+    }
+
+    /// Returns the fresh value for `ns`/`key` and restarts its TTL, for sliding
+    /// expiration. Counts as a hit or miss like `get`; negative entries are ignored.
+    pub async fn touch(&self, ns: &str, key: &str) -> Option<String> {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return None; }
+        let start = self.start_timer();
+        let cache_key = self.config().state_key(ns, key);
+        let value = match self.touch_entry(cache_key.clone(), key).await {
+            Some(entry) => self.decode_entry(&cache_key, entry).await,
+            None => None,
+        };
+        match value {
+            Some(value) => {
+                self.metrics.record_cached_since(start);
+                self.record_hit(ns, key);
+                (!self.config().shadow_mode).then_some(value)
+            }
+            None => {
+                self.record_miss(ns, key);
+                None
+            }
+        }
+    }
+
+    /// Rewrites a fresh entry with `written_at` reset to now, so its TTL starts over.
+    /// While `frozen` the entry is only read.
+    async fn touch_entry(&self, cache_key: String, key: &str) -> Option<StateEntry> {
+        if self.config().frozen {
+            return self.read_entry(&cache_key, key).await.filter(|e| e.is_fresh());
+        }
+        let config = self.config();
+        let mut marker = None;
+        let touch = self.state_cache.entry(cache_key.clone()).and_compute_with(|current| {
+            let op = match current.map(|entry| entry.into_value()) {
+                Some(entry) if !entry.is_for_key(&config, key) => Op::Nop,
+                Some(entry) if !entry.checksum_ok() => Op::Remove,
+                Some(mut entry) if entry.is_fresh() => {
+                    let touched = (cache_key, entry.written_at);
+                    self.touching.lock().unwrap().insert(touched.clone());
+                    marker = Some(touched);
+                    entry.written_at = Instant::now();
+                    Op::Put(entry)
+                }
+                _ => Op::Nop,
+            };
+            async move { op }
+        });
+        let result = self.bounded("touch", touch).await;
+        // Normally consumed by the listener; left behind if the touch timed out
+        if let Some(marker) = marker {
+            self.touching.lock().unwrap().remove(&marker);
+        }
+        let result = result?;
+        match result {
+            CompResult::ReplacedWith(entry) => Some(entry.into_value()),
+            CompResult::Removed(_) => {
+                self.metrics.record_checksum_failure();
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Reads the state entry written for `key`, dropping it as a miss if it fails
+    /// its checksum
+    async fn read_entry(&self, cache_key: &str, key: &str) -> Option<StateEntry> {
+        let entry = self.bounded("get", self.state_cache.get(cache_key)).await.flatten()?;
+        if !entry.is_for_key(&self.config(), key) {
+            return None;
+        }
+        if entry.checksum_ok() {
+            return Some(entry);
+        }
+        self.metrics.record_checksum_failure();
+        tracing::warn!(contract_id = %entry.ns, "dropping state cache entry with a bad checksum");
+        self.bounded("invalidate", self.state_cache.invalidate(cache_key)).await;
+        None
+    }
+
+    /// Decodes a state entry's value, dropping the entry as a miss if it no longer
+    /// decompresses, like `read_entry` does for a bad checksum
+    async fn decode_entry(&self, cache_key: &str, entry: StateEntry) -> Option<String> {
+        if let Some(value) = entry.value.decode() {
+            return Some(value);
+        }
+        self.metrics.record_checksum_failure();
+        tracing::warn!(contract_id = %entry.ns, "dropping state cache entry that fails to decompress");
+        self.bounded("invalidate", self.state_cache.invalidate(cache_key)).await;
+        None
+    }
+
+    /// Time until the entry for `ns`/`key` expires, or `None` if it is not cached
+    pub async fn ttl_remaining(&self, ns: &str, key: &str) -> Option<Duration> {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return None; }
+        let cache_key = self.config().state_key(ns, key);
+        let entry = self.bounded("get", self.state_cache.get(&cache_key)).await.flatten()?;
+        entry.ttl.checked_sub(entry.written_at.elapsed())
+    }
+
+    /// TTL for a new state entry: the override, the contract's rule or `global_ttl`,
+    /// spread by `ttl_jitter`
+    fn effective_ttl(&self, ns: &str, ttl: Option<Duration>) -> Duration {
+        self.config().entry_ttl(ns, ttl, &self.jitter_rng)
+    }
+
+    /// Stores `value` under `ns`/`key`; `ttl` overrides `global_ttl` for this entry only
+    #[tracing::instrument(level = "debug", skip_all, fields(contract_id = %ns, cache.backend = CACHE_BACKEND))]
+    pub async fn put(&self, ns: &str, key: &str, value: String, ttl: Option<Duration>) {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        self.put_entry(ns, key, value, self.effective_ttl(ns, ttl), None).await;
+    }
+
+    /// Like `put`, but records the ledger sequence the value was read at so
+    /// `invalidate_below_ledger` can drop it once the chain moves on
+    pub async fn put_with_ledger(
+        &self,
+        ns: &str,
+        key: &str,
+        value: String,
+        ledger_seq: u32,
+        ttl: Option<Duration>,
+    ) {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        self.put_entry(ns, key, value, self.effective_ttl(ns, ttl), Some(ledger_seq)).await;
+    }
+
+    /// Like `put`, but also files the entry under each of `tags` so related keys
+    /// across contracts can be dropped together with `invalidate_tag`
+    pub async fn put_tagged(
+        &self,
+        ns: &str,
+        key: &str,
+        value: String,
+        tags: &[&str],
+        ttl: Option<Duration>,
+    ) {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        // Index only what was written, so a dropped put leaves no tag behind
+        if !self.put_entry(ns, key, value, self.effective_ttl(ns, ttl), None).await { return; }
+        let cache_key = self.config().state_key(ns, key);
+        let mut index = self.tags.write().unwrap();
+        for tag in tags {
+            index.entry(tag.to_string()).or_default().insert(cache_key.clone());
+        }
+    }
+
+    /// Writes a state entry, returning whether it was stored
+    async fn put_entry(
+        &self,
+        ns: &str,
+        key: &str,
+        value: String,
+        ttl: Duration,
+        ledger_seq: Option<u32>,
+    ) -> bool {
+        if !self.config().enabled || self.skip_frozen() || self.reject_oversize(&value) {
+            return false;
+        }
+        let value = CachedValue::encode(value, &self.config());
+        let entry = StateEntry::new(ns, value, ttl)
+            .with_stale_window(self.config().stale_window)
+            .with_ledger_seq(ledger_seq)
+            .with_original_key(&self.config(), key)
+            .with_checksum(&self.config());
+        let cache_key = self.config().state_key(ns, key);
+        self.bounded("invalidate", self.negative_cache.invalidate(&cache_key)).await;
+        let stored = self.bounded("put", self.state_cache.insert(cache_key, entry)).await.is_some();
+        if stored {
+            self.emit_put(ns, key);
+        }
+        stored
+    }
+
+    /// Runs a state cache operation under `operation_timeout`, if one is set.
+    /// `None` means it ran over; the timeout is counted and logged.
+    async fn bounded<T>(&self, operation: &'static str, op: impl Future<Output = T>) -> Option<T> {
+        let Some(limit) = self.config().operation_timeout else { return Some(op.await) };
+        match tokio::time::timeout(limit, op).await {
+            Ok(value) => Some(value),
+            Err(_) => {
+                self.metrics.record_timeout();
+                tracing::warn!(operation, timeout = ?limit, "state cache operation timed out");
+                None
+            }
+        }
+    }
+
+    fn emit_put(&self, ns: &str, key: &str) {
+        emit_event(&self.events, || CacheEvent::Put {
+            contract_id: ns.to_string(),
+            key: key.to_string(),
+        });
+    }
+
+    /// Returns whether `value` is over `max_value_bytes`, counting the rejection if so
+    fn reject_oversize(&self, value: &str) -> bool {
+        let oversize = self.config().max_value_bytes.is_some_and(|max| value.len() > max);
+        if oversize {
+            self.metrics.record_rejected_oversize();
+            tracing::debug!(len = value.len(), "value too large to cache");
+        }
+        oversize
+    }
+
+    /// Returns whether writes are blocked by `frozen`, counting the skipped write if so
+    fn skip_frozen(&self) -> bool {
+        let frozen = self.config().frozen;
+        if frozen {
+            self.metrics.record_put_skipped_frozen();
+        }
+        frozen
+    }
+
+    /// Freezes or thaws the cache at runtime; see `CacheConfig::frozen`
+    pub fn set_frozen(&self, frozen: bool) {
+        let mut config = self.config.write().unwrap();
+        if config.frozen != frozen {
+            let mut updated = (**config).clone();
+            updated.frozen = frozen;
+            *config = Arc::new(updated);
+            tracing::info!(frozen, "state cache frozen state changed");
+        }
+    }
+
+    /// Like `put`, but returns the fresh value it displaced, e.g. for change
+    /// detection. Reading the old entry and writing the new one happen atomically.
+    pub async fn put_returning_old(
+        &self,
+        ns: &str,
+        key: &str,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> Option<String> {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled || self.skip_frozen() || self.reject_oversize(&value) {
+            return None;
+        }
+        let value = CachedValue::encode(value, &self.config());
+        let entry = StateEntry::new(ns, value, self.effective_ttl(ns, ttl))
+            .with_stale_window(self.config().stale_window)
+            .with_original_key(&self.config(), key)
+            .with_checksum(&self.config());
+        let cache_key = self.config().state_key(ns, key);
+        let mut previous = None;
+        let swap = self.state_cache.entry(cache_key.clone()).and_compute_with(|current| {
+            previous = current
+                .map(|existing| existing.into_value())
+                .filter(|existing| existing.is_fresh())
+                .and_then(|existing| existing.value.decode());
+            async move { Op::Put(entry) }
+        });
+        self.bounded("put", swap).await?;
+        self.bounded("invalidate", self.negative_cache.invalidate(&cache_key)).await;
+        self.emit_put(ns, key);
+        previous
+    }
+
+    /// Stores `value` only if no fresh entry exists for `ns`/`key`, checking and
+    /// inserting atomically; returns whether the value was written
+    pub async fn put_if_absent(
+        &self,
+        ns: &str,
+        key: &str,
+        value: String,
+        ttl: Option<Duration>,
+    ) -> bool {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled || self.skip_frozen() || self.reject_oversize(&value) {
+            return false;
+        }
+        let value = CachedValue::encode(value, &self.config());
+        let entry = StateEntry::new(ns, value, self.effective_ttl(ns, ttl))
+            .with_stale_window(self.config().stale_window)
+            .with_original_key(&self.config(), key)
+            .with_checksum(&self.config());
+        let cache_key = self.config().state_key(ns, key);
+        let insert = self.state_cache.entry(cache_key.clone()).and_compute_with(|current| async move {
+            match current {
+                Some(existing) if existing.value().is_fresh() => Op::Nop,
+                _ => Op::Put(entry),
+            }
+        });
+        let Some(result) = self.bounded("put", insert).await else { return false };
+        let inserted = matches!(result, CompResult::Inserted(_) | CompResult::ReplacedWith(_));
+        if inserted {
+            self.bounded("invalidate", self.negative_cache.invalidate(&cache_key)).await;
+            self.emit_put(ns, key);
+        }
+        inserted
+    }
+
+    /// Replaces the value for `ns`/`key` with `new` only if the fresh cached value
+    /// equals `expected` (`None` expects no fresh entry), checking and writing
+    /// atomically; returns whether the swap happened
+    pub async fn compare_and_set(
+        &self,
+        ns: &str,
+        key: &str,
+        expected: Option<&str>,
+        new: String,
+    ) -> bool {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled || self.skip_frozen() || self.reject_oversize(&new) {
+            return false;
+        }
+        let value = CachedValue::encode(new, &self.config());
+        let entry = StateEntry::new(ns, value, self.effective_ttl(ns, None))
+            .with_stale_window(self.config().stale_window)
+            .with_original_key(&self.config(), key)
+            .with_checksum(&self.config());
+        let cache_key = self.config().state_key(ns, key);
+        let expected = expected.map(str::to_string);
+        let swap = self.state_cache.entry(cache_key.clone()).and_compute_with(|current| async move {
+            let current = current
+                .map(|existing| existing.into_value())
+                .filter(|existing| existing.is_fresh())
+                .and_then(|existing| existing.value.decode());
+            if current == expected {
+                Op::Put(entry)
+            } else {
+                Op::Nop
+            }
+        });
+        let Some(result) = self.bounded("put", swap).await else { return false };
+        let swapped = matches!(result, CompResult::Inserted(_) | CompResult::ReplacedWith(_));
+        if swapped {
+            self.bounded("invalidate", self.negative_cache.invalidate(&cache_key)).await;
+            self.emit_put(ns, key);
+        }
+        swapped
+    }
+
+    /// Stores several entries of one contract, all with the same `ttl` override
+    pub async fn put_many(&self, ns: &str, entries: &[(&str, String)], ttl: Option<Duration>) {
+        if !self.config().enabled { return; }
+        for (key, value) in entries {
+            self.put(ns, key, value.clone(), ttl).await;
+        }
+    }
+
+    /// Records that `ns`/`key` does not exist upstream; `ttl` defaults to `negative_ttl`
+    pub async fn put_negative(&self, ns: &str, key: &str, ttl: Option<Duration>) {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled || self.skip_frozen() { return; }
+        let entry = StateEntry::new(ns, (), ttl.unwrap_or(self.config().negative_ttl));
+        let cache_key = self.config().state_key(ns, key);
+        self.bounded("put", async {
+            self.state_cache.invalidate(&cache_key).await;
+            self.negative_cache.insert(cache_key, entry).await;
+        })
+        .await;
+    }
+
+    /// Drops the entry for `ns`/`key` along with the HTTP responses cached for its
+    /// state path, returning how many entries were removed
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(contract_id = %ns, cache.backend = CACHE_BACKEND, cache.removed = tracing::field::Empty)
+    )]
+    pub async fn invalidate(&self, ns: &str, key: &str) -> usize {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return 0; }
+        let cache_key = self.config().state_key(ns, key);
+        let removed = self
+            .bounded("invalidate", async {
+                self.state_cache.remove(&cache_key).await.is_some() as usize
+                    + self.negative_cache.remove(&cache_key).await.is_some() as usize
+            })
+            .await
+            .unwrap_or(0)
+            + self.remove_responses(ns, |url| state_path_key(url) == Some(key)).await;
+        tracing::Span::current().record("cache.removed", removed);
+        if removed > 0 {
+            emit_event(&self.events, || CacheEvent::Invalidate {
+                contract_id: ns.to_string(),
+                key: Some(key.to_string()),
+            });
+        }
+        removed
+    }
+
+    /// `get` for a typed key
+    pub async fn get_by_key(&self, key: &CacheKey) -> (Option<String>, bool) {
+        self.get(key.contract_id(), key.key()).await
+    }
+
+    /// `put` for a typed key
+    pub async fn put_by_key(&self, key: &CacheKey, value: String, ttl: Option<Duration>) {
+        self.put(key.contract_id(), key.key(), value, ttl).await
+    }
+
+    /// `invalidate` for a typed key
+    pub async fn invalidate_by_key(&self, key: &CacheKey) -> usize {
+        self.invalidate(key.contract_id(), key.key()).await
+    }
+
+    /// Drops every entry written with `tag`, returning how many were removed. A key
+    /// rewritten by a plain `put` or `put_negative` since it was tagged is dropped too.
+    /// Like `invalidate_contract`, each affected contract gets an `InvalidationNotice`.
+    pub async fn invalidate_tag(&self, tag: &str) -> usize {
+        if !self.config().enabled { return 0; }
+        let Some(keys) = self.tags.write().unwrap().remove(tag) else { return 0 };
+        let config = self.config();
+        let removed: Vec<(String, String)> = self
+            .bounded("invalidate", async {
+                let mut removed = Vec::new();
+                for cache_key in &keys {
+                    if let Some(entry) = self.state_cache.remove(cache_key).await {
+                        removed.push((entry.ns.clone(), config.entry_key(cache_key, &entry).to_string()));
+                    }
+                    if let Some(entry) = self.negative_cache.remove(cache_key).await {
+                        removed.push((entry.ns.clone(), config.key_in_ns(&entry.ns, cache_key).to_string()));
+                    }
+                }
+                removed
+            })
+            .await
+            .unwrap_or_default();
+
+        let contracts: HashSet<&str> = removed.iter().map(|(ns, _)| ns.as_str()).collect();
+        for ns in contracts {
+            self.notify_invalidation(ns, "tag");
+        }
+        for (ns, key) in &removed {
+            emit_event(&self.events, || CacheEvent::Invalidate {
+                contract_id: ns.clone(),
+                key: Some(key.clone()),
+            });
+        }
+        removed.len()
+    }
+
+    /// Health of the cache subsystem. Degraded once a background task has panicked;
+    /// the cache still serves requests but expired entries and invalidation events
+    /// are no longer being handled.
+    pub fn health(&self) -> CacheHealth {
+        let background_task_failed = self.task_panicked.load(Ordering::Relaxed);
+        let backend_reachable = true;
+        let status = if backend_reachable && !background_task_failed {
+            CacheHealthStatus::Ok
+        } else {
+            CacheHealthStatus::Degraded
+        };
+        CacheHealth {
+            status,
+            enabled: self.config().enabled,
+            backend_reachable,
+            background_task_failed,
+        }
+    }
+
+    /// Whether the hit rate over `hit_rate_window` is below `hit_rate_floor`, once
+    /// the window holds at least `hit_rate_min_lookups` lookups. Relies on the
+    /// samples taken by `spawn_maintenance`, falling back to lifetime totals.
+    pub fn is_underperforming(&self) -> bool {
+        let config = self.config();
+        let Some(floor) = config.hit_rate_floor else { return false };
+        let (hits, misses) = self.metrics.lookups_since(config.hit_rate_window).unwrap_or((
+            self.metrics.hits.load(Ordering::Relaxed),
+            self.metrics.misses.load(Ordering::Relaxed),
+        ));
+        let lookups = hits + misses;
+        lookups > 0 && lookups >= config.hit_rate_min_lookups && (hits as f64 / lookups as f64) < floor
+    }
+
+    /// Forgets tagged keys whose entries have since expired or been evicted
+    fn prune_tags(&self) {
+        let mut index = self.tags.write().unwrap();
+        for keys in index.values_mut() {
+            keys.retain(|key| self.state_cache.contains_key(key));
+        }
+        index.retain(|_, keys| !keys.is_empty());
+    }
+
+    /// Fallible `get_or_load` guarded by a circuit breaker: after `breaker_threshold`
+    /// consecutive loader errors within `breaker_window`, loads are rejected for
+    /// `breaker_cooldown`, serving a stale entry if one is still retained. Concurrent
+    /// misses share one loader call; an error is returned to all of them and nothing
+    /// is cached. With `serve_stale_on_error`, a failed load falls back to the
+    /// retained stale entry instead.
+    pub async fn try_get_or_load<F, Fut, E>(
+        &self,
+        ns: &str,
+        key: &str,
+        loader: F,
+    ) -> Result<String, LoadError<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+        E: Send + Sync + 'static,
+    {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        let config = self.config();
+        if !config.enabled { return loader().await.map_err(|e| LoadError::Loader(Arc::new(e))); }
+
+        let start = self.start_timer();
+        let cache_key = config.state_key(ns, key);
+        let existing = match self.read_entry(&cache_key, key).await {
+            Some(entry) => self.decode_entry(&cache_key, entry.clone()).await.map(|value| (entry, value)),
+            None => None,
+        };
+        let fresh = existing.as_ref().is_some_and(|(e, _)| e.is_fresh());
+        if fresh {
+            self.metrics.record_cached_since(start);
+            self.record_hit(ns, key);
+        }
+        // Shadow mode counts the hit but goes on to load, as for a stale entry
+        if let Some((_, value)) = existing.as_ref().filter(|_| fresh && !config.shadow_mode) {
+            return Ok(value.clone());
+        }
+
+        if self.circuit_state() == CircuitState::Open {
+            return match existing {
+                Some((_, value)) => {
+                    if !fresh { self.record_hit(ns, key); }
+                    Ok(value)
+                }
+                None => {
+                    self.record_miss(ns, key);
+                    Err(LoadError::CircuitOpen)
+                }
+            };
+        }
+        if existing.is_some() {
+            self.bounded("invalidate", self.state_cache.invalidate(&cache_key)).await;
+        }
+        if !fresh { self.record_miss(ns, key); }
+
+        match self.try_load_coalesced(ns, key, cache_key.clone(), &config, loader).await {
+            Ok(value) => {
+                self.breaker.lock().unwrap().record_success();
+                Ok(value)
+            }
+            Err(e) => {
+                self.breaker.lock().unwrap().record_failure(&config);
+                match existing.filter(|_| config.serve_stale_on_error) {
+                    Some((stale, value)) => {
+                        tracing::warn!(contract_id = %ns, "cache load failed; serving stale value");
+                        self.metrics.record_stale_served();
+                        self.restore_stale(cache_key, stale).await;
+                        Ok(value)
+                    }
+                    None => Err(LoadError::Loader(e)),
+                }
+            }
+        }
+    }
+
+    /// Puts back a stale entry that was dropped to make way for a load that failed,
+    /// so later requests can fall back to it too. It still ages out when its
+    /// original stale window would have ended.
+    async fn restore_stale(&self, cache_key: String, mut entry: StateEntry) {
+        let retained_for = entry
+            .ttl
+            .saturating_add(entry.stale_for)
+            .saturating_sub(entry.written_at.elapsed());
+        if retained_for.is_zero() { return; }
+        entry.written_at = Instant::now();
+        entry.ttl = Duration::ZERO;
+        entry.stale_for = retained_for;
+        self.bounded("put", self.state_cache.entry(cache_key).or_insert(entry)).await;
+    }
+
+    /// Loads through Moka's `try_get_with`, so concurrent misses for `cache_key` share
+    /// one loader call and an error reaches all of them without caching anything.
+    /// A value over `max_value_bytes` is returned to every waiter but not cached. A
+    /// waiter handed another caller's entry that fails to decode loads for itself.
+    async fn try_load_coalesced<F, Fut, E>(
+        &self,
+        ns: &str,
+        key: &str,
+        cache_key: String,
+        config: &CacheConfig,
+        loader: F,
+    ) -> Result<String, Arc<E>>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<String, E>>,
+        E: Send + Sync + 'static,
+    {
+        let ttl = self.effective_ttl(ns, None);
+        let metrics = &self.metrics;
+        // Set once this caller's own load has produced a value
+        let loaded = OnceLock::new();
+        let mut init = std::pin::pin!(async {
+            let _permit = self.acquire_load_permit().await;
+            let start = config.measure_latency.then(Instant::now);
+            let value = loader().await.map_err(|e| UncachedLoad::Loader(Arc::new(e)))?;
+            metrics.record_uncached_since(start);
+            if self.reject_oversize(&value) {
+                return Err(UncachedLoad::Oversize(value));
+            }
+            let entry = StateEntry::new(ns, CachedValue::encode(value.clone(), config), ttl)
+                .with_stale_window(config.stale_window)
+                .with_original_key(config, key)
+                .with_checksum(config);
+            let _ = loaded.set(value);
+            Ok(entry)
+        });
+        loop {
+            let entry = match self.state_cache.try_get_with(cache_key.clone(), init.as_mut()).await {
+                Ok(entry) => entry,
+                Err(failure) => {
+                    return match &*failure {
+                        UncachedLoad::Oversize(value) => Ok(value.clone()),
+                        UncachedLoad::Loader(e) => {
+                            self.metrics.record_loader_error();
+                            Err(Arc::clone(e))
+                        }
+                    }
+                }
+            };
+            if let Some(value) = loaded.get() {
+                return Ok(value.clone());
+            }
+            if let Some(value) = self.decode_entry(&cache_key, entry).await {
+                return Ok(value);
+            }
+            // `init` has not been polled yet, so the next round can still run it
+        }
+    }
+
+    /// Like `try_get_or_load` without the circuit breaker, but refreshes ahead: a hit
+    /// on an entry with less than `refresh_ahead` left to live returns the cached
+    /// value and reloads it in the background, so hot keys rarely block on a miss.
+    /// At most one refresh per key runs at a time; a failed refresh keeps the old
+    /// entry until it expires.
+    pub async fn get_with_refresh<F, Fut, E>(
+        self: &Arc<Self>,
+        ns: &str,
+        key: &str,
+        loader: F,
+    ) -> Result<String, Arc<E>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<String, E>> + Send + 'static,
+        E: std::fmt::Display + Send + Sync + 'static,
+    {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        let config = self.config();
+        if !config.enabled { return loader().await.map_err(Arc::new); }
+
+        let start = self.start_timer();
+        let cache_key = config.state_key(ns, key);
+        match self.read_entry(&cache_key, key).await {
+            Some(entry) if entry.is_fresh() && config.shadow_mode => {
+                self.record_hit(ns, key);
+                self.bounded("invalidate", self.state_cache.invalidate(&cache_key)).await;
+                return self.try_load_coalesced(ns, key, cache_key, &config, loader).await;
+            }
+            Some(entry) if entry.is_fresh() => {
+                let remaining = entry.ttl.saturating_sub(entry.written_at.elapsed());
+                let Some(value) = self.decode_entry(&cache_key, entry).await else {
+                    self.record_miss(ns, key);
+                    return self.try_load_coalesced(ns, key, cache_key, &config, loader).await;
+                };
+                self.metrics.record_cached_since(start);
+                self.record_hit(ns, key);
+                if remaining < config.refresh_ahead {
+                    let metrics = self.metrics.clone();
+                    let refresh = loader();
+                    self.spawn_refresh(ns, key, cache_key, async move {
+                        match refresh.await {
+                            Ok(value) => Some(value),
+                            Err(e) => {
+                                metrics.record_loader_error();
+                                tracing::warn!("cache refresh-ahead load failed: {}", e);
+                                None
+                            }
+                        }
+                    });
+                }
+                return Ok(value);
+            }
+            Some(_) => {
+                self.bounded("invalidate", self.state_cache.invalidate(&cache_key)).await;
+            }
+            None => {}
+        }
+        self.record_miss(ns, key);
+        self.try_load_coalesced(ns, key, cache_key, &config, loader).await
+    }
+
+    /// Waits for a loader slot when `max_concurrent_loads` is set. Taken inside
+    /// Moka's init futures, so callers coalesced onto one key share a slot.
+    async fn acquire_load_permit(&self) -> Option<tokio::sync::SemaphorePermit<'_>> {
+        self.load_permits.as_ref()?.acquire().await.ok()
+    }
+
+    /// Caches the value produced by `load` in the background, unless a refresh of
+    /// `cache_key` is already in flight. `None` leaves the current entry alone.
+    fn spawn_refresh<Fut>(self: &Arc<Self>, ns: &str, key: &str, cache_key: String, load: Fut)
+    where
+        Fut: Future<Output = Option<String>> + Send + 'static,
+    {
+        if !self.refreshing.lock().unwrap().insert(cache_key.clone()) { return; }
+        let layer = Arc::clone(self);
+        let ns = ns.to_string();
+        let key = key.to_string();
+        tokio::spawn(async move {
+            let permit = layer.acquire_load_permit().await;
+            let start = layer.start_timer();
+            let loaded = load.await;
+            drop(permit);
+            if let Some(value) = loaded {
+                layer.metrics.record_uncached_since(start);
+                layer.put(&ns, &key, value, None).await;
+            }
+            layer.refreshing.lock().unwrap().remove(&cache_key);
+        });
+    }
+
+    /// Current state of the circuit breaker used by `try_get_or_load`
+    pub fn circuit_state(&self) -> CircuitState {
+        self.breaker.lock().unwrap().state()
+    }
+
+    /// Returns the cached value for `ns`/`key`, running `loader` on a miss. Concurrent
+    /// misses for the same key are coalesced so the loader runs only once.
+    pub async fn get_or_load<F, Fut>(&self, ns: &str, key: &str, loader: F) -> String
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = String>,
+    {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return loader().await; }
+
+        let start = self.start_timer();
+        let cache_key = self.config().state_key(ns, key);
+        match self.read_entry(&cache_key, key).await {
+            Some(entry) if entry.is_fresh() && !self.config().shadow_mode => {
+                let Some(value) = self.decode_entry(&cache_key, entry).await else {
+                    self.record_miss(ns, key);
+                    return self.load_coalesced(ns, key, cache_key, loader).await;
+                };
+                self.metrics.record_cached_since(start);
+                self.record_hit(ns, key);
+                return value;
+            }
+            // Shadow mode counts the hit but reloads, as for a stale entry
+            Some(entry) if entry.is_fresh() => {
+                self.record_hit(ns, key);
+                self.bounded("invalidate", self.state_cache.invalidate(&cache_key)).await;
+            }
+            // A stale entry would otherwise be returned by get_with below
+            Some(_) => {
+                self.record_miss(ns, key);
+                self.bounded("invalidate", self.state_cache.invalidate(&cache_key)).await;
+            }
+            None => self.record_miss(ns, key),
+        }
+        self.load_coalesced(ns, key, cache_key, loader).await
+    }
+
+    /// `try_load_coalesced` for a loader that cannot fail
+    async fn load_coalesced<F, Fut>(&self, ns: &str, key: &str, cache_key: String, loader: F) -> String
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = String>,
+    {
+        let config = self.config();
+        let infallible = || async { Ok::<_, std::convert::Infallible>(loader().await) };
+        match self.try_load_coalesced(ns, key, cache_key, &config, infallible).await {
+            Ok(value) => value,
+            Err(never) => match *never {},
+        }
+    }
+
+    /// Like `get_or_load`, for sources that can report a key as absent. A `None`
+    /// from `loader` is cached negatively so repeat lookups skip the loader until
+    /// `negative_ttl` elapses. Concurrent misses are not coalesced.
+    pub async fn get_or_load_optional<F, Fut>(&self, ns: &str, key: &str, loader: F) -> Option<String>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Option<String>>,
+    {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if let Some(cached) = self.lookup(ns, key).await {
+            return cached;
+        }
+
+        let permit = self.acquire_load_permit().await;
+        let start = self.start_timer();
+        let value = loader().await;
+        self.metrics.record_uncached_since(start);
+        drop(permit);
+        match &value {
+            Some(v) => self.put(ns, key, v.clone(), None).await,
+            None => self.put_negative(ns, key, None).await,
+        }
+        value
+    }
+
+    /// Serves an expired entry that is still inside `stale_window` immediately and
+    /// reloads it in the background; at most one refresh per key runs at a time.
+    /// Falls back to a blocking `get_or_load` when nothing is cached.
+    pub async fn get_stale_while_revalidate<F, Fut>(
+        self: &Arc<Self>,
+        ns: &str,
+        key: &str,
+        loader: F,
+    ) -> String
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = String> + Send + 'static,
+    {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return loader().await; }
+
+        let cache_key = self.config().state_key(ns, key);
+        let stale = match self.read_entry(&cache_key, key).await {
+            Some(_) if self.config().shadow_mode => {
+                self.record_hit(ns, key);
+                self.bounded("invalidate", self.state_cache.invalidate(&cache_key)).await;
+                return self.load_coalesced(ns, key, cache_key, loader).await;
+            }
+            Some(entry) => entry,
+            None => return self.get_or_load(ns, key, loader).await,
+        };
+        let fresh = stale.is_fresh();
+        let Some(value) = self.decode_entry(&cache_key, stale).await else {
+            return self.get_or_load(ns, key, loader).await;
+        };
+        self.record_hit(ns, key);
+        if !fresh {
+            self.spawn_refresh(ns, key, cache_key, async move { Some(loader().await) });
+        }
+        value
+    }
+
+    pub fn record_uncached_latency(&self, latency: Duration) {
+        if self.config().measure_latency {
+            self.metrics.record_uncached_latency(latency);
+        }
+    }
+
+    /// Start of a latency measurement, or `None` when `measure_latency` is off
+    fn start_timer(&self) -> Option<Instant> {
+        self.config().measure_latency.then(Instant::now)
+    }
+
+    /// Ages of the oldest and newest state entries, for TTL tuning. Scans the whole
+    /// state cache; zero ages when it is empty.
+    pub async fn entry_age_stats(&self) -> AgeStats {
+        let mut stats = AgeStats::default();
+        for (_, entry) in self.state_cache.iter() {
+            let age = entry.written_at.elapsed();
+            if stats.count == 0 {
+                stats.oldest = age;
+                stats.newest = age;
+            } else {
+                stats.oldest = stats.oldest.max(age);
+                stats.newest = stats.newest.min(age);
+            }
+            stats.count += 1;
+        }
+        stats
+    }
+
+    /// Lists the keys currently cached under `ns`, sorted, for debugging. Scans the
+    /// whole state cache, so keep it off hot paths.
+    pub async fn keys_for_contract(&self, ns: &str) -> Vec<String> {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        let config = self.config();
+        let mut keys: Vec<String> = self
+            .state_cache
+            .iter()
+            .filter(|(_, entry)| entry.ns == ns)
+            .map(|(key, entry)| config.entry_key(&key, &entry).to_string())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    /// Drops every state entry cached under `ns`, returning how many were removed.
+    /// Notifies `invalidation_webhook`, if set, from a background task.
+    pub async fn invalidate_contract(&self, ns: &str) -> usize {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return 0; }
+        let removed = self.remove_contract_entries(ns).await;
+        self.notify_invalidation(ns, "explicit");
+        emit_event(&self.events, || CacheEvent::Invalidate {
+            contract_id: ns.to_string(),
+            key: None,
+        });
+        removed
+    }
+
+    /// Drops the entries under `ns` whose key starts with `key_prefix`, such as
+    /// every `balance:` key of a contract, along with the HTTP responses cached for
+    /// those keys' state paths, returning how many were removed. Like
+    /// `invalidate_contract`, the contract gets an `InvalidationNotice`.
+    pub async fn invalidate_prefix(&self, ns: &str, key_prefix: &str) -> usize {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return 0; }
+        let config = self.config();
+        let keys: Vec<Arc<String>> = self
+            .state_cache
+            .iter()
+            .filter(|(key, entry)| {
+                entry.ns == ns && config.entry_key(key, entry).starts_with(key_prefix)
+            })
+            .map(|(key, _)| key)
+            .collect();
+        let negative_keys: Vec<Arc<String>> = self
+            .negative_cache
+            .iter()
+            .filter(|(key, entry)| {
+                entry.ns == ns && config.key_in_ns(ns, key).starts_with(key_prefix)
+            })
+            .map(|(key, _)| key)
+            .collect();
+
+        let removed: Vec<String> = self
+            .bounded("invalidate", async {
+                let mut removed = Vec::new();
+                for cache_key in &keys {
+                    if let Some(entry) = self.state_cache.remove(cache_key.as_str()).await {
+                        removed.push(config.entry_key(cache_key, &entry).to_string());
+                    }
+                }
+                for cache_key in &negative_keys {
+                    if self.negative_cache.remove(cache_key.as_str()).await.is_some() {
+                        removed.push(config.key_in_ns(ns, cache_key).to_string());
+                    }
+                }
+                removed
+            })
+            .await
+            .unwrap_or_default();
+        let responses = self
+            .remove_responses(ns, |url| {
+                state_path_key(url).is_some_and(|key| key.starts_with(key_prefix))
+            })
+            .await;
+
+        self.notify_invalidation(ns, "prefix");
+        for key in &removed {
+            emit_event(&self.events, || CacheEvent::Invalidate {
+                contract_id: ns.to_string(),
+                key: Some(key.clone()),
+            });
+        }
+        removed.len() + responses
+    }
+
+    /// Queues an `InvalidationNotice` for `ns` when a webhook is configured
+    fn notify_invalidation(&self, ns: &str, reason: &str) {
+        let Some(url) = self.config().invalidation_webhook.clone() else { return };
+        let client = self
+            .webhook_client
+            .get_or_init(|| {
+                reqwest::Client::builder()
+                    .timeout(Duration::from_secs(10))
+                    .build()
+                    .unwrap_or_default()
+            })
+            .clone();
+        let notice = InvalidationNotice {
+            contract_id: ns.to_string(),
+            reason: reason.to_string(),
+            ts: unix_millis(),
+        };
+        tokio::spawn(send_invalidation_webhook(client, url, notice));
+    }
+
+    async fn remove_contract_entries(&self, ns: &str) -> usize {
+        self.bounded("invalidate", async {
+            let keys: Vec<Arc<String>> = self
+                .state_cache
+                .iter()
+                .filter(|(_, entry)| entry.ns == ns)
+                .map(|(key, _)| key)
+                .collect();
+            for key in &keys {
+                self.state_cache.invalidate(key.as_str()).await;
+            }
+            let negative_keys: Vec<Arc<String>> = self
+                .negative_cache
+                .iter()
+                .filter(|(_, entry)| entry.ns == ns)
+                .map(|(key, _)| key)
+                .collect();
+            for key in &negative_keys {
+                self.negative_cache.invalidate(key.as_str()).await;
+            }
+            let response_keys: Vec<Arc<String>> = self
+                .response_cache
+                .iter()
+                .filter(|(_, entry)| entry.ns == ns)
+                .map(|(key, _)| key)
+                .collect();
+            for key in &response_keys {
+                self.response_cache.invalidate(key.as_str()).await;
+            }
+            keys.len() + negative_keys.len() + response_keys.len()
+        })
+        .await
+        .unwrap_or(0)
+    }
+
+    /// The response cached under `ns` for `url` (path and query). Lookups are
+    /// counted apart from the state cache's hits and misses, like `get_abi`.
+    pub async fn get_response(&self, ns: &str, url: &str) -> Option<CachedResponse> {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return None; }
+        let cache_key = self.config().state_key(ns, url);
+        let entry = self.bounded("get", self.response_cache.get(&cache_key)).await.flatten();
+        let result = entry.filter(|e| e.is_fresh()).map(|e| e.value);
+        if result.is_some() {
+            crate::metrics::RESPONSE_CACHE_HITS.inc();
+        } else {
+            crate::metrics::RESPONSE_CACHE_MISSES.inc();
+        }
+        result
+    }
+
+    /// Caches `response` for `url` under `ns` with the contract's usual TTL
+    pub async fn put_response(&self, ns: &str, url: &str, response: CachedResponse) {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled || self.skip_frozen() { return; }
+        let config = self.config();
+        let entry = StateEntry::new(ns, response, self.effective_ttl(ns, None))
+            .with_original_key(&config, url);
+        self.bounded("put", self.response_cache.insert(config.state_key(ns, url), entry)).await;
+    }
+
+    /// Drops the cached responses under `ns` for `path` with any query string,
+    /// returning how many were removed
+    pub async fn invalidate_responses(&self, ns: &str, path: &str) -> usize {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return 0; }
+        self.remove_responses(ns, |url| {
+            url == path || url.strip_prefix(path).is_some_and(|rest| rest.starts_with('?'))
+        })
+        .await
+    }
+
+    /// Drops the cached responses under `ns` whose URL satisfies `matches`,
+    /// returning how many were removed
+    async fn remove_responses(&self, ns: &str, matches: impl Fn(&str) -> bool) -> usize {
+        let config = self.config();
+        let keys: Vec<Arc<String>> = self
+            .response_cache
+            .iter()
+            .filter(|(key, entry)| entry.ns == ns && matches(config.entry_key(key, entry)))
+            .map(|(key, _)| key)
+            .collect();
+        self.bounded("invalidate", async {
+            for key in &keys {
+                self.response_cache.invalidate(key.as_str()).await;
+            }
+            keys.len()
+        })
+        .await
+        .unwrap_or(0)
+    }
+
+    /// Drops entries under `ns` that were cached at a ledger older than `ledger_seq`,
+    /// returning how many were removed. Entries stored without a ledger are kept.
+    pub async fn invalidate_below_ledger(&self, ns: &str, ledger_seq: u32) -> usize {
+        let ns = self.canonical_ns(ns);
+        let ns: &str = &ns;
+        if !self.config().enabled { return 0; }
+        let keys: Vec<Arc<String>> = self
+            .state_cache
+            .iter()
+            .filter(|(_, entry)| {
+                entry.ns == ns && entry.ledger_seq.is_some_and(|seq| seq < ledger_seq)
+            })
+            .map(|(key, _)| key)
+            .collect();
+        self.bounded("invalidate", async {
+            for key in &keys {
+                self.state_cache.invalidate(key.as_str()).await;
+            }
+            keys.len()
+        })
+        .await
+        .unwrap_or(0)
+    }
+
+    /// Drops every entry from all caches, e.g. after loading a new ledger snapshot
+    pub async fn clear(&self) {
+        if !self.config().enabled { return; }
+        self.abi_cache.invalidate_all();
+        self.verification_cache.invalidate_all();
+        self.state_cache.invalidate_all();
+        self.negative_cache.invalidate_all();
+        self.response_cache.invalidate_all();
+        self.tags.write().unwrap().clear();
+    }
+
+    /// Applies pending writes, evictions and expirations on every cache. Moka defers
+    /// this work, so call it before `entry_count` or `estimated_size_bytes` when the
+    /// numbers need to reflect the latest writes.
+    pub async fn run_pending(&self) {
+        self.abi_cache.run_pending_tasks().await;
+        self.verification_cache.run_pending_tasks().await;
+        self.state_cache.run_pending_tasks().await;
+        self.negative_cache.run_pending_tasks().await;
+        self.response_cache.run_pending_tasks().await;
+    }
+
+    /// Shutdown signal and completion guard for a new background task
+    fn background_task_handles(&self) -> (watch::Receiver<bool>, Option<mpsc::Sender<()>>) {
+        (self.shutdown_tx.subscribe(), self.task_guard.lock().unwrap().clone())
+    }
+
+    /// Spawns a background task whose panic is reported by `health`
+    fn spawn_watched<F>(&self, task: F) -> tokio::task::JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        tokio::spawn(PanicWatch {
+            task: Box::pin(task),
+            panicked: self.task_panicked.clone(),
+        })
+    }
+
+    /// Stops the maintenance and invalidation tasks, waits for them to exit and then
+    /// applies Moka's pending writes and evictions, so counts and snapshots taken
+    /// afterwards are exact. The cache stays usable for reads and writes.
+    pub async fn shutdown(&self) {
+        self.shutdown_tx.send_replace(true);
+        self.task_guard.lock().unwrap().take();
+        // Yields None once every background task has dropped its guard
+        self.tasks_done.lock().await.recv().await;
+        self.run_pending().await;
+    }
+
+    /// Runs the `auto_tune` loop, adjusting `global_ttl` every `adjust_interval`
+    /// from the hit rate since the previous check. Returns `None` when auto-tuning
+    /// is off. Like maintenance, it exits on `shutdown` or once the layer is dropped.
+    pub fn spawn_auto_tune(self: Arc<Self>) -> Option<tokio::task::JoinHandle<()>> {
+        let period = self.config().auto_tune.as_ref()?.adjust_interval;
+        let (mut shutdown, guard) = self.background_task_handles();
+        let layer = Arc::downgrade(&self);
+        let mut baseline = self.lookup_totals();
+
+        Some(self.spawn_watched(async move {
+            let _guard = guard;
+            if *shutdown.borrow() { return; }
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown.changed() => break,
+                }
+                let Some(layer) = layer.upgrade() else { break };
+                let totals = layer.lookup_totals();
+                let hits = totals.0.saturating_sub(baseline.0);
+                let lookups = hits + totals.1.saturating_sub(baseline.1);
+                baseline = totals;
+                if lookups > 0 {
+                    layer.auto_tune_ttl(hits as f64 / lookups as f64);
+                }
+            }
+        }))
+    }
+
+    /// Lifetime state cache hits and misses
+    fn lookup_totals(&self) -> (usize, usize) {
+        (
+            self.metrics.hits.load(Ordering::Relaxed),
+            self.metrics.misses.load(Ordering::Relaxed),
+        )
+    }
+
+    /// One auto-tune adjustment of `global_ttl` for an observed `hit_rate`
+    fn auto_tune_ttl(&self, hit_rate: f64) {
+        let mut config = self.config.write().unwrap();
+        let Some(tune) = config.auto_tune.clone() else { return };
+        let ttl = config.global_ttl;
+        let tuned = if hit_rate < tune.target_hit_rate - AUTO_TUNE_TOLERANCE {
+            let used = self.state_cache.weighted_size() as f64;
+            if used >= config.max_capacity as f64 * AUTO_TUNE_CAPACITY_HEADROOM {
+                // Entries are already being pushed out for space; a longer TTL won't help
+                return;
+            }
+            ttl.mul_f64(AUTO_TUNE_STEP)
+        } else if hit_rate > tune.target_hit_rate + AUTO_TUNE_TOLERANCE {
+            ttl.div_f64(AUTO_TUNE_STEP)
+        } else {
+            return;
+        };
+        let tuned = tuned.clamp(tune.min_ttl, tune.max_ttl);
+        if tuned != ttl {
+            let mut updated = (**config).clone();
+            updated.global_ttl = tuned;
+            *config = Arc::new(updated);
+            tracing::info!(hit_rate, from = ?ttl, to = ?tuned, "auto-tuned state cache TTL");
+        }
+    }
+
+    /// Moka only purges expired entries while handling cache traffic, so an idle
+    /// cache keeps holding them. This task runs that housekeeping every
+    /// `sweep_interval` and exits on `shutdown` or once the layer has been dropped.
+    pub fn spawn_maintenance(self: Arc<Self>) -> tokio::task::JoinHandle<()> {
+        let period = self.config().sweep_interval;
+        let (mut shutdown, guard) = self.background_task_handles();
+        let layer = Arc::downgrade(&self);
+
+        self.spawn_watched(async move {
+            let _guard = guard;
+            if *shutdown.borrow() { return; }
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                tokio::select! {
+                    _ = interval.tick() => {}
+                    _ = shutdown.changed() => break,
+                }
+                let Some(layer) = layer.upgrade() else { break };
+                layer.run_pending().await;
+                layer.prune_tags();
+                layer.prune_contract_counters();
+                layer.metrics.sample();
+                if layer.is_underperforming() {
+                    let config = layer.config();
+                    tracing::warn!(
+                        hit_rate = layer.metrics.hit_rate_since(config.hit_rate_window),
+                        floor = config.hit_rate_floor,
+                        "State cache hit rate is below the configured floor; consider a longer TTL or more capacity"
+                    );
+                }
+            }
+        })
+    }
+
+    /// Copies every fresh state entry, with its remaining TTL, into `target`, e.g. to
+    /// move onto a layer built with a different `max_capacity`. With `clear_source`
+    /// the state caches here are emptied afterwards. Negative entries are not copied.
+    /// Returns how many entries were copied.
+    pub async fn drain_into(&self, target: &CacheLayer, clear_source: bool) -> usize {
+        // Moka applies writes lazily; settle them so iteration sees every entry
+        self.run_pending().await;
+        let config = self.config();
+        let entries: Vec<(String, StateEntry)> = self
+            .state_cache
+            .iter()
+            .filter(|(_, entry)| entry.is_fresh() && entry.checksum_ok())
+            .map(|(cache_key, entry)| (config.entry_key(&cache_key, &entry).to_string(), entry))
+            .collect();
+
+        let mut copied = 0;
+        for (key, entry) in entries {
+            let Some(ttl) = entry.ttl.checked_sub(entry.written_at.elapsed()).filter(|t| !t.is_zero()) else {
+                continue;
+            };
+            let Some(value) = entry.value.decode() else {
+                self.metrics.record_checksum_failure();
+                continue;
+            };
+            target.put_entry(&entry.ns, &key, value, ttl, entry.ledger_seq).await;
+            copied += 1;
+        }
+
+        if clear_source {
+            self.state_cache.invalidate_all();
+            self.negative_cache.invalidate_all();
+            self.tags.write().unwrap().clear();
+        }
+        tracing::info!(copied, clear_source, "drained state cache into another layer");
+        copied
+    }
+
+    /// Writes every fresh state entry, with its remaining TTL, to `path` as JSON so a
+    /// restarted process can pick up where this one left off. Returns the entry count.
+    pub async fn save_snapshot(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<usize> {
+        let entries: Vec<SnapshotEntry> = self
+            .state_cache
+            .iter()
+            .filter_map(|(cache_key, entry)| {
+                let ttl_remaining = entry.ttl.checked_sub(entry.written_at.elapsed())?;
+                if !entry.checksum_ok() {
+                    return None;
+                }
+                let value = entry.value.clone().decode()?;
+                Some(SnapshotEntry {
+                    key: self.config().entry_key(&cache_key, &entry).to_string(),
+                    ns: entry.ns.clone(),
+                    checksum: entry.checksum.map(|_| crc32fast::hash(value.as_bytes())),
+                    value,
+                    ttl_remaining_ms: ttl_remaining.as_millis() as u64,
+                    ledger_seq: entry.ledger_seq,
+                })
+            })
+            .collect();
+        let snapshot = CacheSnapshot { saved_at_ms: unix_millis(), entries };
+
+        // Write to a sibling file first so a crash mid-write never leaves a torn snapshot
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        tokio::fs::write(&tmp_path, serde_json::to_vec(&snapshot)?).await?;
+        tokio::fs::rename(&tmp_path, path).await?;
+        Ok(snapshot.entries.len())
+    }
+
+    /// Repopulates the state cache from a `save_snapshot` file. Time spent on disk
+    /// counts against each entry's TTL and entries that have run out are skipped.
+    /// Returns how many entries were loaded.
+    pub async fn load_snapshot(&self, path: impl AsRef<std::path::Path>) -> std::io::Result<usize> {
+        if !self.config().enabled { return Ok(0); }
+        let snapshot: CacheSnapshot = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+        let age_ms = unix_millis().saturating_sub(snapshot.saved_at_ms);
+
+        let mut loaded = 0;
+        for entry in snapshot.entries {
+            let Some(ttl_ms) = entry.ttl_remaining_ms.checked_sub(age_ms).filter(|ms| *ms > 0) else {
+                continue;
+            };
+            if entry.checksum.is_some_and(|checksum| checksum != crc32fast::hash(entry.value.as_bytes())) {
+                self.metrics.record_checksum_failure();
+                tracing::warn!(contract_id = %entry.ns, "skipping corrupt cache snapshot entry");
+                continue;
+            }
+            let ttl = Duration::from_millis(ttl_ms);
+            self.put_entry(&entry.ns, &entry.key, entry.value, ttl, entry.ledger_seq).await;
+            loaded += 1;
+        }
+        Ok(loaded)
+    }
+
+    /// Invalidates a contract's cached state for every event received on `rx`. If the
+    /// listener lags behind the bus, the state caches are cleared since the missed
+    /// contracts are unknown. Exits when the bus closes, on `shutdown`, or once the
+    /// layer has been dropped.
+    pub fn spawn_invalidation_listener(
+        self: Arc<Self>,
+        mut rx: broadcast::Receiver<InvalidationEvent>,
+    ) -> tokio::task::JoinHandle<()> {
+        let (mut shutdown, guard) = self.background_task_handles();
+        let layer = Arc::downgrade(&self);
+
+        self.spawn_watched(async move {
+            let _guard = guard;
+            if *shutdown.borrow() { return; }
+            loop {
+                let event = tokio::select! {
+                    event = rx.recv() => event,
+                    _ = shutdown.changed() => break,
+                };
+                let Some(layer) = layer.upgrade() else { break };
+                match event {
+                    Ok(event) => {
+                        // Every instance sees the event, so only direct calls fire the webhook
+                        let contract_id = layer.canonical_ns(&event.contract_id).into_owned();
+                        let removed = layer.remove_contract_entries(&contract_id).await;
+                        tracing::debug!(
+                            contract_id = %event.contract_id,
+                            removed,
+                            "cache invalidated by contract event"
+                        );
+                    }
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        tracing::warn!(missed, "cache invalidation listener lagged; clearing state cache");
+                        layer.state_cache.invalidate_all();
+                        layer.negative_cache.invalidate_all();
+                        layer.response_cache.invalidate_all();
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        })
+    }
+
+    /// Loads each `(ns, key)` through `loader` and caches the results, running at most
+    /// `WARM_CONCURRENCY` loads at a time. Failed loads are logged and counted.
+    pub async fn warm<F, Fut, E>(self: &Arc<Self>, keys: &[(String, String)], loader: F) -> WarmReport
+    where
+        F: Fn(String, String) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String, E>> + Send + 'static,
+        E: std::fmt::Display + Send + 'static,
+    {
+        let mut report = WarmReport::default();
+        if !self.config().enabled { return report; }
+
+        let loader = Arc::new(loader);
+        let mut tasks = tokio::task::JoinSet::new();
+        for (ns, key) in keys {
+            if tasks.len() >= WARM_CONCURRENCY {
+                report.record(tasks.join_next().await);
+            }
+            let (ns, key) = (ns.clone(), key.clone());
+            let layer = Arc::clone(self);
+            let loader = loader.clone();
+            tasks.spawn(async move {
+                match loader(ns.clone(), key.clone()).await {
+                    Ok(value) => {
+                        layer.put(&ns, &key, value, None).await;
+                        true
+                    }
+                    Err(e) => {
+                        tracing::warn!(ns = %ns, key = %key, "cache warm load failed: {}", e);
+                        false
+                    }
+                }
+            });
+        }
+        while let Some(result) = tasks.join_next().await {
+            report.record(Some(result));
+        }
+
+        tracing::info!(warmed = report.warmed, failed = report.failed, "cache warm finished");
+        report
+    }
+
+    /// Starts an asynchronous startup warmup task querying the top 100 contracts
+    pub fn warm_up(self: Arc<Self>, pool: PgPool) {
+        if !self.config().enabled { return; }
+        tokio::spawn(async move {
+            tracing::info!("Starting startup cache warmup...");
+            // Query top 100 contracts by query frequency from contract_interactions or just get contracts
+            let top_contracts: Vec<(String, Option<String>)> = sqlx::query_as(
+                r#"
+                SELECT c.contract_id, c.wasm_hash
+                FROM contracts c
+                LEFT JOIN contract_interactions ci ON c.id = ci.contract_id
+                GROUP BY c.id
+                ORDER BY COUNT(ci.id) DESC
+                LIMIT 100
+                "#
+            )
+            .fetch_all(&pool)
+            .await
+            .unwrap_or_default();
+
+            for (contract_id, wasm_hash) in top_contracts {
+                if let Ok(Some(abi)) = sqlx::query_scalar::<_, serde_json::Value>(
+                    "SELECT abi FROM contract_abis WHERE contract_id = $1 ORDER BY created_at DESC LIMIT 1"
+                )
+                .bind(&contract_id)
+                .fetch_optional(&pool).await {
+                    self.abi_cache.insert(contract_id.clone(), abi.to_string()).await;
+                }
+
+                if let Some(w_hash) = wasm_hash {
+                    if let Ok(Some(ver_res)) = sqlx::query_scalar::<_, String>(
+                        "SELECT status::text FROM formal_verification_results LIMIT 1" // fallback fake
+                    ).fetch_optional(&pool).await {
+                        self.verification_cache.insert(w_hash.clone(), ver_res).await;
+                    }
+                }
+            }
+            tracing::info!("Completed startup cache warmup.");
+        });
+    }
+}
+
+/// Namespaced cache for non-`String` values such as raw XDR bytes or decoded structs.
+/// Uses the same key encoding and TTL rules (`per_contract`, `ttl_jitter`) as
+/// `CacheLayer::put`, and keeps its own `CacheMetrics`. Since values have no
+/// intrinsic byte size, `max_capacity` counts entries and `max_value_bytes` is
+/// ignored unless the layer is built `with_weigher`.
+pub struct TypedCacheLayer<V: Clone + Send + Sync + 'static> {
+    cache: MokaCache<String, StateEntry<V>>,
+    config: CacheConfig,
+    metrics: CacheMetrics,
+    jitter_rng: Mutex<StdRng>,
+    weigh: Option<fn(&V) -> usize>,
+}
+
+impl<V: Clone + Send + Sync + 'static> TypedCacheLayer<V> {
+    pub fn new(config: CacheConfig) -> Self {
+        let cache = MokaCache::builder()
+            .max_capacity(config.max_capacity)
+            .expire_after(StateExpiry)
+            .build();
+
+        Self::from_parts(cache, config, None)
+    }
+
+    /// Builds a layer whose capacity is weighed in bytes, counting the key plus
+    /// `weigh(value)` like the state cache does for strings
+    pub fn with_weigher(config: CacheConfig, weigh: fn(&V) -> usize) -> Self {
+        let cache = MokaCache::builder()
+            .max_capacity(config.max_capacity)
+            .weigher(move |k: &String, v: &StateEntry<V>| -> u32 {
+                (k.len() + weigh(&v.value)).try_into().unwrap_or(u32::MAX)
+            })
+            .expire_after(StateExpiry)
+            .build();
+
+        Self::from_parts(cache, config, Some(weigh))
+    }
+
+    fn from_parts(
+        cache: MokaCache<String, StateEntry<V>>,
+        config: CacheConfig,
+        weigh: Option<fn(&V) -> usize>,
+    ) -> Self {
+        Self {
+            cache,
+            metrics: CacheMetrics::with_ewma_alpha(config.latency_ewma_alpha),
+            jitter_rng: config.jitter_rng(),
+            config,
+            weigh,
+        }
+    }
+
+    pub fn config(&self) -> &CacheConfig {
+        &self.config
+    }
+
+    pub fn metrics(&self) -> &CacheMetrics {
+        &self.metrics
+    }
+
+    /// Weighted size of the cached entries; bytes when built `with_weigher`
+    pub fn estimated_size_bytes(&self) -> u64 {
+        self.cache.weighted_size()
+    }
+
+    /// Applies Moka's pending writes, evictions and expirations
+    pub async fn run_pending(&self) {
+        self.cache.run_pending_tasks().await;
+    }
+
+    pub async fn get(&self, ns: &str, key: &str) -> Option<V> {
+        if !self.config.enabled { return None; }
+        let result = self
+            .cache
+            .get(&self.config.state_key(ns, key))
+            .await
+            .filter(|e| e.is_fresh())
+            .map(|e| e.value);
+        if result.is_some() {
+            self.metrics.record_hit();
+        } else {
+            self.metrics.record_miss();
+        }
+        result
+    }
+
+    /// Stores `value` under `ns`/`key`; `ttl` overrides the contract's rule or
+    /// `global_ttl` for this entry only
+    pub async fn put(&self, ns: &str, key: &str, value: V, ttl: Option<Duration>) {
+        if !self.config.enabled { return; }
+        let oversize = self
+            .weigh
+            .zip(self.config.max_value_bytes)
+            .is_some_and(|(weigh, max)| weigh(&value) > max);
+        if oversize {
+            self.metrics.record_rejected_oversize();
+            return;
+        }
+        let entry = StateEntry::new(ns, value, self.config.entry_ttl(ns, ttl, &self.jitter_rng));
+        self.cache.insert(self.config.state_key(ns, key), entry).await;
+    }
+
+    pub async fn invalidate(&self, ns: &str, key: &str) {
+        if !self.config.enabled { return; }
+        self.cache.invalidate(&self.config.state_key(ns, key)).await;
+    }
+
+    pub async fn clear(&self) {
+        if !self.config.enabled { return; }
+        self.cache.invalidate_all();
+    }
+}
+
+/// Caches raw bytes, such as ledger entry XDR, without a base64 round-trip
+pub type BytesCacheLayer = TypedCacheLayer<Vec<u8>>;
+
+impl BytesCacheLayer {
+    /// A byte cache whose capacity counts the actual value lengths
+    pub fn for_bytes(config: CacheConfig) -> Self {
+        Self::with_weigher(config, Vec::len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_abi_cache() {
+        let config = CacheConfig {
+            enabled: true,
+            max_capacity: 100,
+            ..Default::default()
+        };
+        let cache = CacheLayer::new(config);
+
+        cache.put_abi("contract_1", "abi_json_1".to_string()).await;
+
+        let val = cache.get_abi("contract_1").await;
+        assert_eq!(val, Some("abi_json_1".to_string()));
+
+        cache.invalidate_abi("contract_1").await;
+        
+        let val2 = cache.get_abi("contract_1").await;
+        assert!(val2.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_verification_cache() {
+        let config = CacheConfig {
+            enabled: true,
+            max_capacity: 100,
+            ..Default::default()
+        };
+        let cache = CacheLayer::new(config);
+
+        cache.put_verification("hash_1", "result_1".to_string()).await;
+        
+        let val = cache.get_verification("hash_1").await;
+        assert_eq!(val, Some("result_1".to_string()));
+        
+        cache.invalidate_verification("hash_1").await;
+        
+        let val2 = cache.get_verification("hash_1").await;
+        assert!(val2.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_disabled_cache() {
+        let config = CacheConfig {
+            enabled: false,
+            max_capacity: 100,
+            ..Default::default()
+        };
+        let cache = CacheLayer::new(config);
+
+        cache.put_abi("c1", "v1".to_string()).await;
+        let val = cache.get_abi("c1").await;
+        assert!(val.is_none());
+
+        cache.put_verification("h1", "v1".to_string()).await;
+        let val2 = cache.get_verification("h1").await;
+        assert!(val2.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_state_cache_ttl_override() {
+        let config = CacheConfig {
+            enabled: true,
+            max_capacity: 100,
+            global_ttl: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let cache = CacheLayer::new(config);
+
+        cache.put("c1", "short", "v1".to_string(), Some(Duration::from_millis(50))).await;
+        cache.put("c1", "long", "v2".to_string(), None).await;
+
+        let (val, hit) = cache.get("c1", "short").await;
+        assert!(hit);
+        assert_eq!(val, Some("v1".to_string()));
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let (val, hit) = cache.get("c1", "short").await;
+        assert!(!hit);
+        assert!(val.is_none());
+
+        let (val, hit) = cache.get("c1", "long").await;
+        assert!(hit);
+        assert_eq!(val, Some("v2".to_string()));
+    }
+
+    #[test]
+    fn test_try_new_rejects_zero_capacity() {
+        let config = CacheConfig {
+            max_capacity: 0,
+            ..Default::default()
+        };
+        assert_eq!(CacheLayer::try_new(config).err(), Some(CacheConfigError::ZeroCapacity));
+
+        assert!(CacheLayer::try_new(CacheConfig::default()).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_clear() {
+        let cache = CacheLayer::new(CacheConfig::default());
+
+        cache.put_abi("contract_1", "abi".to_string()).await;
+        cache.put_verification("hash_1", "result".to_string()).await;
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        cache.put("c2", "k2", "v2".to_string(), None).await;
+
+        cache.clear().await;
+
+        assert!(cache.get_abi("contract_1").await.is_none());
+        assert!(cache.get_verification("hash_1").await.is_none());
+        assert!(!cache.get("c1", "k1").await.1);
+        assert!(!cache.get("c2", "k2").await.1);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_contract() {
+        let cache = CacheLayer::new(CacheConfig::default());
+
+        cache.put("c1", "k1", "a".to_string(), None).await;
+        cache.put("c1", "k2", "b".to_string(), None).await;
+        cache.put("c2", "k1", "c".to_string(), None).await;
+
+        assert_eq!(cache.invalidate_contract("c1").await, 2);
+
+        assert!(!cache.get("c1", "k1").await.1);
+        assert!(!cache.get("c1", "k2").await.1);
+        assert_eq!(cache.get("c2", "k1").await.0, Some("c".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_state_key_no_collision() {
+        let cache = CacheLayer::new(CacheConfig::default());
+
+        cache.put("a:b", "c", "first".to_string(), None).await;
+        cache.put("a", "b:c", "second".to_string(), None).await;
+
+        assert_eq!(cache.get("a:b", "c").await.0, Some("first".to_string()));
+        assert_eq!(cache.get("a", "b:c").await.0, Some("second".to_string()));
+        let config = cache.config();
+        assert_ne!(config.state_key("a:b", "c"), config.state_key("a", "b:c"));
+    }
+
+    #[tokio::test]
+    async fn test_entry_count_and_size() {
+        let cache = CacheLayer::new(CacheConfig::default());
+
+        cache.put_abi("c1", "abi".to_string()).await;
+        cache.put_verification("h1", "ok".to_string()).await;
+        cache.put("ns", "k1", "value".to_string(), None).await;
+
+        cache.abi_cache.run_pending_tasks().await;
+        cache.verification_cache.run_pending_tasks().await;
+        cache.state_cache.run_pending_tasks().await;
+
+        assert_eq!(cache.entry_count(), 3);
+        let expected = ("c1".len() + "abi".len())
+            + ("h1".len() + "ok".len())
+            + (cache.config().state_key("ns", "k1").len() + "value".len());
+        assert_eq!(cache.estimated_size_bytes(), expected as u64);
+    }
+
+    #[tokio::test]
+    async fn test_eviction_count() {
+        let config = CacheConfig {
+            max_capacity: 100,
+            ..Default::default()
+        };
+        let cache = CacheLayer::new(config);
+
+        for i in 0..20 {
+            cache.put("c1", &format!("k{}", i), "x".repeat(20), None).await;
+        }
+        cache.state_cache.run_pending_tasks().await;
+
+        assert!(cache.metrics().eviction_count() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_typed_cache_bytes() {
+        let cache: TypedCacheLayer<Vec<u8>> = TypedCacheLayer::new(CacheConfig::default());
+        let xdr = vec![0x00, 0x00, 0x00, 0x06, 0xff, 0x10];
+
+        cache.put("c1", "ledger_entry", xdr.clone(), None).await;
+        assert_eq!(cache.get("c1", "ledger_entry").await, Some(xdr));
+
+        cache.invalidate("c1", "ledger_entry").await;
+        assert!(cache.get("c1", "ledger_entry").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_or_load_single_flight() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let loads = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = Vec::new();
+        for _ in 0..50 {
+            let cache = cache.clone();
+            let loads = loads.clone();
+            handles.push(tokio::spawn(async move {
+                cache
+                    .get_or_load("c1", "hot", || async move {
+                        loads.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        "loaded".to_string()
+                    })
+                    .await
+            }));
+        }
+
+        for handle in handles {
+            assert_eq!(handle.await.unwrap(), "loaded");
+        }
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert_eq!(cache.metrics().uncached_count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_config_serde_round_trip() {
+        let yaml = "enabled: false\nglobal_ttl_secs: 120\nmax_capacity: 5000\n";
+        let config: CacheConfig = serde_yaml::from_str(yaml).unwrap();
+        assert!(!config.enabled);
+        assert_eq!(config.global_ttl, Duration::from_secs(120));
+        assert_eq!(config.max_capacity, 5000);
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["global_ttl_secs"], 120);
+        let back: CacheConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(back.global_ttl, config.global_ttl);
+
+        let partial: CacheConfig = serde_yaml::from_str("max_capacity: 10").unwrap();
+        assert!(partial.enabled);
+        assert_eq!(partial.global_ttl, CacheConfig::default().global_ttl);
+    }
+
+    #[test]
+    fn test_config_accepts_duration_strings() {
+        let yaml = "global_ttl_secs: 500ms\nnegative_ttl_secs: 90s\nsweep_interval_secs: 1h30m\n";
+        let config: CacheConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.global_ttl, Duration::from_millis(500));
+        assert_eq!(config.negative_ttl, Duration::from_secs(90));
+        assert_eq!(config.sweep_interval, Duration::from_secs(5400));
+
+        let json = serde_json::to_value(&config).unwrap();
+        assert_eq!(json["global_ttl_secs"], "500ms");
+        assert_eq!(json["negative_ttl_secs"], 90);
+        let back: CacheConfig = serde_json::from_value(json).unwrap();
+        assert_eq!(back.global_ttl, Duration::from_millis(500));
+
+        let rule: ContractCacheRule = serde_json::from_str(r#"{"ttl_secs": "250ms"}"#).unwrap();
+        assert_eq!(rule.ttl, Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn test_config_rejects_invalid_duration_strings() {
+        for bad in ["", "90", "5 minutes", "ms", "10y"] {
+            let json = format!(r#"{{"global_ttl_secs": "{}"}}"#, bad);
+            let err = serde_json::from_str::<CacheConfig>(&json).unwrap_err();
+            assert!(err.to_string().contains("invalid duration"), "{}: {}", bad, err);
+        }
+    }
+
+    #[test]
+    fn test_config_builder() {
+        let config = CacheConfig::builder()
+            .enabled(false)
+            .capacity(42)
+            .ttl(Duration::from_secs(7))
+            .build();
+        assert!(!config.enabled);
+        assert_eq!(config.max_capacity, 42);
+        assert_eq!(config.global_ttl, Duration::from_secs(7));
+
+        let defaults = CacheConfig::builder().build();
+        assert_eq!(defaults.max_capacity, CacheConfig::default().max_capacity);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_load_records_latency() {
+        let cache = CacheLayer::new(CacheConfig::default());
+
+        let value = cache
+            .get_or_load("c1", "k1", || async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                "fresh".to_string()
+            })
+            .await;
+        assert_eq!(value, "fresh");
+        assert_eq!(cache.metrics().uncached_count.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.metrics().cached_count.load(Ordering::Relaxed), 0);
+        assert!(cache.metrics().avg_uncached_latency() >= 10_000.0);
+        assert!(cache.metrics().improvement_factor().is_none());
+
+        let value = cache
+            .get_or_load("c1", "k1", || async { "reloaded".to_string() })
+            .await;
+        assert_eq!(value, "fresh");
+        assert_eq!(cache.metrics().uncached_count.load(Ordering::Relaxed), 1);
+        assert_eq!(cache.metrics().cached_count.load(Ordering::Relaxed), 1);
+        assert!(cache.metrics().improvement_factor().unwrap() > 1.0);
+    }
+
+    #[test]
+    fn test_improvement_factor_without_data() {
+        let metrics = CacheMetrics::default();
+        assert_eq!(metrics.improvement_factor(), None);
+
+        metrics.record_uncached_latency(Duration::from_millis(100));
+        assert_eq!(metrics.improvement_factor(), None);
+
+        metrics.record_cached_latency(Duration::from_millis(1));
+        let factor = metrics.improvement_factor().unwrap();
+        assert!((factor - 100.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let metrics = CacheMetrics::default();
+        for _ in 0..95 {
+            metrics.record_uncached_latency(Duration::from_micros(100));
+        }
+        for _ in 0..5 {
+            metrics.record_uncached_latency(Duration::from_millis(100));
+        }
+
+        let mean = metrics.avg_uncached_latency();
+        assert!(metrics.uncached_latency_p50() < mean);
+        assert!(metrics.uncached_latency_p99() > 10.0 * mean);
+        assert!(metrics.uncached_latency_p99() >= 100_000.0);
+        assert_eq!(metrics.cached_latency_p99(), 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_export_prometheus() {
+        let registry = prometheus::Registry::new_custom(Some("cachetest".into()), None).unwrap();
+        crate::metrics::register_all(&registry).unwrap();
+
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        for _ in 0..3 {
+            cache.get("c1", "k1").await;
+        }
+        cache.get("c1", "missing").await;
+        assert_eq!(cache.metrics().hits.load(Ordering::Relaxed), 3);
+        assert_eq!(cache.metrics().misses.load(Ordering::Relaxed), 1);
+
+        cache.metrics().export_prometheus();
+
+        let text = crate::metrics::gather_metrics(&registry);
+        let ratio: f64 = text
+            .lines()
+            .find_map(|line| line.strip_prefix("cachetest_cache_hit_ratio "))
+            .expect("hit ratio line")
+            .parse()
+            .unwrap();
+        assert!((ratio - 0.75).abs() < f64::EPSILON);
+        assert!(text.contains("cachetest_cache_hits_total"));
+        assert!(text.contains("cachetest_cache_avg_cached_latency_micros"));
+    }
+
+    #[test]
+    fn test_metrics_snapshot() {
+        let metrics = CacheMetrics::default();
+        metrics.record_hit();
+        metrics.record_hit();
+        metrics.record_miss();
+        metrics.record_cached_latency(Duration::from_micros(2));
+        metrics.record_uncached_latency(Duration::from_millis(1));
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.hits, 2);
+        assert_eq!(snapshot.misses, 1);
+        assert_eq!(snapshot.hit_rate, metrics.hit_rate());
+        assert_eq!(snapshot.avg_cached_latency_us, metrics.avg_cached_latency());
+        assert_eq!(snapshot.avg_uncached_latency_us, metrics.avg_uncached_latency());
+        assert_eq!(snapshot.improvement_factor, metrics.improvement_factor());
+
+        let json = serde_json::to_value(&snapshot).unwrap();
+        assert_eq!(json["hits"], 2);
+        assert_eq!(json["evictions"], 0);
+    }
+
+    #[tokio::test]
+    async fn test_reset_metrics_keeps_data() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        cache.get("c1", "k1").await;
+        cache.get("c1", "missing").await;
+        assert!(cache.metrics().hit_rate() > 0.0);
+
+        cache.reset_metrics();
+        assert_eq!(cache.metrics().hit_rate(), 0.0);
+        assert_eq!(cache.metrics().cached_count.load(Ordering::Relaxed), 0);
+        assert_eq!(cache.metrics().cached_latency_p99(), 0.0);
+
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v1".to_string()));
+        assert_eq!(cache.metrics().hits.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_purges_expired_entries() {
+        let config = CacheConfig::builder()
+            .sweep_interval(Duration::from_millis(50))
+            .build();
+        let cache = Arc::new(CacheLayer::new(config));
+
+        for i in 0..3 {
+            cache
+                .put("c1", &format!("k{}", i), "v".to_string(), Some(Duration::from_millis(20)))
+                .await;
+        }
+        let handle = cache.clone().spawn_maintenance();
+
+        // Moka's timer wheel only processes per-entry expirations about once a second
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert_eq!(cache.entry_count(), 0);
+
+        drop(cache);
+        tokio::time::timeout(Duration::from_secs(1), handle)
+            .await
+            .expect("maintenance task exits after the layer is dropped")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_capacity_is_weighted_by_bytes() {
+        let config = CacheConfig::builder().capacity(1_000).build();
+        let cache = CacheLayer::new(config);
+
+        for i in 0..5 {
+            cache.put("c1", &format!("k{}", i), "x".repeat(400), None).await;
+        }
+        cache.state_cache.run_pending_tasks().await;
+
+        // Five entries fit an entry-count limit of 1000 but not a 1000-byte budget
+        assert!(cache.estimated_size_bytes() <= 1_000);
+        assert!(cache.entry_count() < 5);
+    }
+
+    #[tokio::test]
+    async fn test_negative_caching() {
+        let config = CacheConfig::builder()
+            .negative_ttl(Duration::from_millis(50))
+            .build();
+        let cache = CacheLayer::new(config);
+        let counter = AtomicUsize::new(0);
+        let loads = &counter;
+        let loader = move || async move {
+            loads.fetch_add(1, Ordering::SeqCst);
+            None
+        };
+
+        assert_eq!(cache.lookup("c1", "gone").await, None);
+        assert_eq!(cache.get_or_load_optional("c1", "gone", loader).await, None);
+        assert_eq!(cache.lookup("c1", "gone").await, Some(None));
+        assert_eq!(cache.get("c1", "gone").await, (None, true));
+
+        assert_eq!(cache.get_or_load_optional("c1", "gone", loader).await, None);
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(cache.lookup("c1", "gone").await, None);
+        assert_eq!(cache.get_or_load_optional("c1", "gone", loader).await, None);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+
+        cache.put("c1", "gone", "back".to_string(), None).await;
+        assert_eq!(cache.lookup("c1", "gone").await, Some(Some("back".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_ttl_jitter_spreads_expiry() {
+        let config = CacheConfig::builder()
+            .ttl(Duration::from_secs(60))
+            .ttl_jitter(Duration::from_secs(10))
+            .build();
+        let cache = CacheLayer::new(config);
+
+        let mut ttls = std::collections::HashSet::new();
+        for i in 0..50 {
+            let key = format!("k{}", i);
+            cache.put("c1", &key, "v".to_string(), None).await;
+            let entry = cache.state_cache.get(&cache.config().state_key("c1", &key)).await.unwrap();
+            assert!(entry.ttl >= Duration::from_secs(50) && entry.ttl <= Duration::from_secs(70));
+            ttls.insert(entry.ttl);
+        }
+        assert!(ttls.len() > 1);
+    }
+
+    #[tokio::test]
+    async fn test_ttl_jitter_is_capped_for_short_ttls() {
+        let config = CacheConfig::builder()
+            .ttl(Duration::from_secs(60))
+            .ttl_jitter(Duration::from_secs(10))
+            .build();
+        let cache = CacheLayer::new(config);
+
+        for i in 0..50 {
+            let key = format!("k{}", i);
+            cache.put("c1", &key, "v".to_string(), Some(Duration::from_secs(1))).await;
+            let entry = cache.state_cache.get(&cache.config().state_key("c1", &key)).await.unwrap();
+            assert!(entry.ttl >= Duration::from_millis(500) && entry.ttl <= Duration::from_millis(1500));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stale_while_revalidate() {
+        let config = CacheConfig::builder()
+            .stale_window(Duration::from_secs(5))
+            .build();
+        let cache = Arc::new(CacheLayer::new(config));
+
+        cache.put("c1", "k1", "old".to_string(), Some(Duration::from_millis(50))).await;
+        tokio::time::sleep(Duration::from_millis(80)).await;
+
+        // Expired for regular reads, but still served stale while refreshing
+        assert!(!cache.get("c1", "k1").await.1);
+        let value = cache
+            .get_stale_while_revalidate("c1", "k1", || async { "new".to_string() })
+            .await;
+        assert_eq!(value, "old");
+
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(cache.get("c1", "k1").await.0, Some("new".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_get_many_and_put_many() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache
+            .put_many("c1", &[("a", "1".to_string()), ("c", "3".to_string())], None)
+            .await;
+
+        let values = cache.get_many("c1", &["a", "b", "c"]).await;
+        assert_eq!(values, vec![Some("1".to_string()), None, Some("3".to_string())]);
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.hits.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.misses.load(Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_put_if_absent() {
+        let cache = CacheLayer::new(CacheConfig::default());
+
+        assert!(cache.put_if_absent("c1", "k1", "first".to_string(), None).await);
+        assert!(!cache.put_if_absent("c1", "k1", "second".to_string(), None).await);
+        assert_eq!(cache.get("c1", "k1").await.0, Some("first".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_ttl_remaining() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        assert_eq!(cache.ttl_remaining("c1", "k1").await, None);
+
+        cache.put("c1", "k1", "v1".to_string(), Some(Duration::from_secs(10))).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let remaining = cache.ttl_remaining("c1", "k1").await.unwrap();
+        assert!(remaining <= Duration::from_millis(9_900));
+        assert!(remaining > Duration::from_millis(9_000));
+    }
+
+    #[tokio::test]
+    async fn test_namespace_prefix_isolates_keys() {
+        let staging = CacheLayer::new(CacheConfig::builder().namespace_prefix("staging").build());
+        let prod = CacheLayer::new(CacheConfig::builder().namespace_prefix("prod").build());
+
+        let staging_key = staging.config().state_key("c1", "k1");
+        let prod_key = prod.config().state_key("c1", "k1");
+        assert_eq!(staging_key, "staging:2:c1:k1");
+        assert_ne!(staging_key, prod_key);
+
+        // Simulate a shared backend by copying staging's raw entry into prod under the same key
+        staging.put("c1", "k1", "staging-value".to_string(), None).await;
+        let entry = staging.state_cache.get(&staging_key).await.unwrap();
+        prod.state_cache.insert(staging_key, entry).await;
+        assert_eq!(prod.get("c1", "k1").await.0, None);
+    }
+
+    #[test]
+    fn test_try_new_rejects_empty_key_separator() {
+        let config = CacheConfig::builder().key_separator("").build();
+        assert_eq!(
+            CacheLayer::try_new(config).err(),
+            Some(CacheConfigError::EmptyKeySeparator)
+        );
+    }
+
+    #[test]
+    fn test_try_new_rejects_digit_leading_key_separator() {
+        // With "0", ("0000000000", k) and ("0", "0000000000" + k) would share a key
+        let config = CacheConfig::builder().key_separator("0").build();
+        assert_eq!(
+            CacheLayer::try_new(config).err(),
+            Some(CacheConfigError::DigitLeadingKeySeparator)
+        );
+        assert!(CacheConfig::builder().key_separator("|0").build().validate().is_ok());
+    }
+
+    #[test]
+    fn test_validate_rejects_invalid_ttls() {
+        let config = CacheConfig::builder().ttl(Duration::ZERO).build();
+        assert_eq!(config.validate(), Err(CacheConfigError::ZeroTtl));
+
+        let config = CacheConfig::builder()
+            .ttl(Duration::from_secs(10))
+            .ttl_jitter(Duration::from_secs(10))
+            .build();
+        assert_eq!(
+            config.validate(),
+            Err(CacheConfigError::JitterExceedsTtl {
+                jitter: Duration::from_secs(10),
+                ttl: Duration::from_secs(10),
+            })
+        );
+
+        let config = CacheConfig::builder()
+            .contract_rule("c1", ContractCacheRule { ttl: Some(Duration::ZERO) })
+            .build();
+        assert_eq!(
+            config.validate(),
+            Err(CacheConfigError::ZeroContractTtl("c1".to_string()))
+        );
+
+        let config = CacheConfig::builder()
+            .ttl(Duration::from_secs(60))
+            .ttl_jitter(Duration::from_secs(10))
+            .contract_rule("c1", ContractCacheRule { ttl: Some(Duration::from_secs(5)) })
+            .build();
+        assert_eq!(
+            config.validate(),
+            Err(CacheConfigError::JitterExceedsTtl {
+                jitter: Duration::from_secs(10),
+                ttl: Duration::from_secs(5),
+            })
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_zero_breaker_threshold() {
+        let config = CacheConfig::builder()
+            .circuit_breaker(0, Duration::from_secs(30), Duration::from_secs(30))
+            .build();
+        assert_eq!(
+            CacheLayer::try_new(config).err(),
+            Some(CacheConfigError::ZeroBreakerThreshold)
+        );
+
+        let config = CacheConfig {
+            sweep_interval: Duration::ZERO,
+            ..Default::default()
+        };
+        assert_eq!(config.validate(), Err(CacheConfigError::ZeroSweepInterval));
+    }
+
+    #[tokio::test]
+    async fn test_removal_listener() {
+        let config = CacheConfig {
+            max_capacity: 100,
+            ..Default::default()
+        };
+        let cache = CacheLayer::new(config);
+        let removed = Arc::new(Mutex::new(Vec::new()));
+        let sink = removed.clone();
+        cache.on_removal(move |contract_id, key, cause| {
+            sink.lock().unwrap().push((contract_id, key, cause));
+        });
+
+        cache.put("c1", "pinned", "x".to_string(), None).await;
+        cache.invalidate("c1", "pinned").await;
+        for i in 0..20 {
+            cache.put("c1", &format!("k{}", i), "x".repeat(20), None).await;
+        }
+        cache.state_cache.run_pending_tasks().await;
+
+        let removed = removed.lock().unwrap();
+        assert!(removed.contains(&("c1".to_string(), "pinned".to_string(), RemovalCause::Explicit)));
+        assert!(removed
+            .iter()
+            .any(|(contract_id, key, cause)| contract_id == "c1"
+                && key.starts_with('k')
+                && *cause == RemovalCause::Size));
+    }
+
+    #[tokio::test]
+    async fn test_compression_round_trip() {
+        let config = CacheConfig::builder()
+            .compression(CompressionKind::Lz4, 1024)
+            .build();
+        let cache = CacheLayer::new(config);
+        let xdr = "AAAABgAAAAE=".repeat(1_000);
+
+        cache.put("c1", "state", xdr.clone(), None).await;
+        assert_eq!(cache.get("c1", "state").await.0, Some(xdr.clone()));
+
+        let key = cache.config().state_key("c1", "state");
+        let entry = cache.state_cache.get(&key).await.unwrap();
+        assert!(matches!(entry.value, CachedValue::Compressed(CompressionKind::Lz4, _)));
+        assert!(entry.value.stored_len() < xdr.len());
+
+        // Small values stay uncompressed
+        cache.put("c1", "small", "tiny".to_string(), None).await;
+        let key = cache.config().state_key("c1", "small");
+        let entry = cache.state_cache.get(&key).await.unwrap();
+        assert!(matches!(entry.value, CachedValue::Plain(_)));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_below_ledger() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put_with_ledger("c1", "old", "v1".to_string(), 100, None).await;
+        cache.put_with_ledger("c1", "new", "v2".to_string(), 200, None).await;
+        cache.put("c1", "untracked", "v3".to_string(), None).await;
+        cache.put_with_ledger("c2", "old", "v4".to_string(), 100, None).await;
+
+        assert_eq!(cache.invalidate_below_ledger("c1", 150).await, 1);
+
+        assert!(!cache.get("c1", "old").await.1);
+        assert_eq!(cache.get("c1", "new").await.0, Some("v2".to_string()));
+        assert!(cache.get("c1", "untracked").await.1);
+        assert!(cache.get("c2", "old").await.1);
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_listener() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let bus = InvalidationBus::new(16);
+        cache.clone().spawn_invalidation_listener(bus.subscribe());
+
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        cache.put("c1", "k2", "v2".to_string(), None).await;
+        cache.put("c2", "k1", "v3".to_string(), None).await;
+
+        assert_eq!(bus.publish("c1"), 1);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(!cache.get("c1", "k1").await.1);
+        assert!(!cache.get("c1", "k2").await.1);
+        assert!(cache.get("c2", "k1").await.1);
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let path = std::env::temp_dir().join(format!("cache-snapshot-{}.json", std::process::id()));
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), Some(Duration::from_secs(60))).await;
+        cache.put_with_ledger("c2", "k2", "v2".to_string(), 42, Some(Duration::from_secs(30))).await;
+        cache.put("c1", "short", "gone".to_string(), Some(Duration::from_millis(20))).await;
+        tokio::time::sleep(Duration::from_millis(40)).await;
+
+        assert_eq!(cache.save_snapshot(&path).await.unwrap(), 2);
+
+        let restored = CacheLayer::new(CacheConfig::default());
+        assert_eq!(restored.load_snapshot(&path).await.unwrap(), 2);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(restored.get("c1", "k1").await.0, Some("v1".to_string()));
+        assert_eq!(restored.get("c2", "k2").await.0, Some("v2".to_string()));
+        assert!(!restored.get("c1", "short").await.1);
+
+        let ttl = restored.ttl_remaining("c1", "k1").await.unwrap();
+        assert!(ttl > Duration::from_secs(58) && ttl <= Duration::from_secs(60));
+        let ttl = restored.ttl_remaining("c2", "k2").await.unwrap();
+        assert!(ttl > Duration::from_secs(28) && ttl <= Duration::from_secs(30));
+        assert_eq!(restored.invalidate_below_ledger("c2", 43).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_warm_keys() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let mut keys: Vec<(String, String)> =
+            (0..40).map(|i| ("c1".to_string(), format!("k{}", i))).collect();
+        keys.push(("c1".to_string(), "broken".to_string()));
+
+        let report = cache
+            .warm(&keys, |ns, key| async move {
+                if key == "broken" {
+                    Err(format!("rpc error for {}", key))
+                } else {
+                    Ok(format!("{}:{}", ns, key))
+                }
+            })
+            .await;
+
+        assert_eq!(report, WarmReport { warmed: 40, failed: 1 });
+        for (ns, key) in &keys[..40] {
+            assert_eq!(cache.get(ns, key).await.0, Some(format!("{}:{}", ns, key)));
+        }
+        assert!(!cache.get("c1", "broken").await.1);
+    }
+
+    #[tokio::test]
+    async fn test_per_contract_ttl_rules() {
+        let config = CacheConfig::builder()
+            .contract_rule("volatile", ContractCacheRule { ttl: Some(Duration::from_millis(50)) })
+            .contract_rule("registry", ContractCacheRule { ttl: Some(Duration::from_secs(3600)) })
+            .build();
+        let cache = CacheLayer::new(config);
+
+        cache.put("volatile", "k", "v".to_string(), None).await;
+        cache.put("registry", "k", "v".to_string(), None).await;
+        cache.put("other", "k", "v".to_string(), None).await;
+
+        let registry_ttl = cache.ttl_remaining("registry", "k").await.unwrap();
+        assert!(registry_ttl > Duration::from_secs(3500));
+        let other_ttl = cache.ttl_remaining("other", "k").await.unwrap();
+        assert!(other_ttl <= Duration::from_secs(300));
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert!(!cache.get("volatile", "k").await.1);
+        assert!(cache.get("registry", "k").await.1);
+
+        // An explicit override still wins over the contract rule
+        cache.put("registry", "short", "v".to_string(), Some(Duration::from_secs(1))).await;
+        assert!(cache.ttl_remaining("registry", "short").await.unwrap() <= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn test_update_config_toggles_enabled() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        assert!(cache.get("c1", "k1").await.1);
+        let response = CachedResponse {
+            status: axum::http::StatusCode::OK,
+            headers: axum::http::HeaderMap::new(),
+            body: axum::body::Bytes::from_static(b"v1"),
+        };
+        cache.put_response("c1", "/state/k1", response).await;
+
+        let disabled = CacheConfig { enabled: false, ..CacheConfig::default() };
+        cache.update_config(disabled).await.unwrap();
+        assert!(!cache.config().enabled);
+        assert!(!cache.get("c1", "k1").await.1);
+        cache.put("c1", "k1", "ignored".to_string(), None).await;
+
+        cache.update_config(CacheConfig::default()).await.unwrap();
+        assert!(!cache.get("c1", "k1").await.1);
+        assert!(cache.get_response("c1", "/state/k1").await.is_none());
+        cache.put("c1", "k1", "v2".to_string(), None).await;
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_update_config_rejects_capacity_change() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        let resized = CacheConfig { max_capacity: 42, ..CacheConfig::default() };
+        assert_eq!(
+            cache.update_config(resized).await,
+            Err(CacheConfigError::Immutable("max_capacity"))
+        );
+        assert_eq!(cache.config().max_capacity, CacheConfig::default().max_capacity);
+    }
+
+    #[tokio::test]
+    async fn test_per_contract_metrics() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("hot", "k", "v".to_string(), None).await;
+        for _ in 0..9 {
+            cache.get("hot", "k").await;
+        }
+        cache.get("hot", "missing").await;
+        for i in 0..4 {
+            cache.get("cold", &format!("k{}", i)).await;
+        }
+        cache.put("cold", "k0", "v".to_string(), None).await;
+        cache.get("cold", "k0").await;
+
+        let hot = cache.contract_metrics("hot").unwrap();
+        assert_eq!((hot.hits, hot.misses), (9, 1));
+        let cold = cache.contract_metrics("cold").unwrap();
+        assert_eq!((cold.hits, cold.misses), (1, 4));
+        assert!(hot.hit_rate > cold.hit_rate);
+        assert!(cache.contract_metrics("unknown").is_none());
+
+        let top = cache.top_contracts_by_misses(1);
+        assert_eq!(top.len(), 1);
+        assert_eq!(top[0].0, "cold");
+
+        // Global counters still see every lookup
+        assert_eq!(cache.metrics().hits.load(Ordering::Relaxed), 10);
+        assert_eq!(cache.metrics().misses.load(Ordering::Relaxed), 5);
+    }
+
+    #[tokio::test]
+    async fn test_bytes_cache_non_utf8() {
+        let cache = BytesCacheLayer::for_bytes(CacheConfig::default());
+        let xdr = vec![0xff, 0xfe, 0x00, 0xc3, 0x28, 0x80];
+        assert!(String::from_utf8(xdr.clone()).is_err());
+
+        cache.put("c1", "ledger_entry", xdr.clone(), None).await;
+        assert_eq!(cache.get("c1", "ledger_entry").await, Some(xdr.clone()));
+
+        cache.cache.run_pending_tasks().await;
+        let key_len = cache.config().state_key("c1", "ledger_entry").len();
+        assert_eq!(cache.estimated_size_bytes(), (key_len + xdr.len()) as u64);
+    }
+
+    #[tokio::test]
+    async fn test_typed_cache_applies_config_rules() {
+        let config = CacheConfig::builder()
+            .contract_rule("volatile", ContractCacheRule { ttl: Some(Duration::from_millis(50)) })
+            .max_value_bytes(4)
+            .build();
+        let cache = BytesCacheLayer::for_bytes(config);
+
+        cache.put("volatile", "k", vec![1], None).await;
+        cache.put("other", "k", vec![1], None).await;
+        cache.put("other", "large", vec![0; 5], None).await;
+        tokio::time::sleep(Duration::from_millis(80)).await;
+
+        assert_eq!(cache.get("volatile", "k").await, None);
+        assert_eq!(cache.get("other", "k").await, Some(vec![1]));
+        assert_eq!(cache.get("other", "large").await, None);
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.hits, snapshot.misses, snapshot.rejected_oversize), (1, 2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_flushes_and_stops_tasks() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let maintenance = cache.clone().spawn_maintenance();
+        let bus = InvalidationBus::new(4);
+        let listener = cache.clone().spawn_invalidation_listener(bus.subscribe());
+
+        for i in 0..50 {
+            cache.put("c1", &format!("k{}", i), "v".to_string(), None).await;
+        }
+        cache.shutdown().await;
+        assert_eq!(cache.entry_count(), 50);
+
+        // Both tasks exit even though the layer and the bus are still alive
+        for task in [maintenance, listener] {
+            tokio::time::timeout(Duration::from_secs(1), task)
+                .await
+                .expect("background task exits on shutdown")
+                .unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_opens_after_failures() {
+        let config = CacheConfig::builder()
+            .circuit_breaker(3, Duration::from_secs(10), Duration::from_secs(60))
+            .build();
+        let cache = CacheLayer::new(config);
+        let calls = AtomicUsize::new(0);
+        let calls = &calls;
+        let failing = move || async move {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<String, _>("rpc unavailable")
+        };
+
+        for _ in 0..3 {
+            let result = cache.try_get_or_load("c1", "k1", failing).await;
+            assert!(matches!(result, Err(LoadError::Loader(_))));
+        }
+        assert_eq!(cache.circuit_state(), CircuitState::Open);
+        assert_eq!(cache.metrics().snapshot().loader_errors, 3);
+
+        // Open circuit fails fast without calling the loader
+        for _ in 0..5 {
+            let result = cache.try_get_or_load("c1", "k1", failing).await;
+            assert!(matches!(result, Err(LoadError::CircuitOpen)));
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_half_open_recovers() {
+        let config = CacheConfig::builder()
+            .circuit_breaker(1, Duration::from_secs(10), Duration::from_millis(50))
+            .build();
+        let cache = CacheLayer::new(config);
+
+        let result = cache.try_get_or_load("c1", "k1", || async { Err::<String, _>("down") }).await;
+        assert!(result.is_err());
+        assert_eq!(cache.circuit_state(), CircuitState::Open);
+
+        tokio::time::sleep(Duration::from_millis(80)).await;
+        assert_eq!(cache.circuit_state(), CircuitState::HalfOpen);
+        let value = cache
+            .try_get_or_load("c1", "k1", || async { Ok::<_, &str>("v1".to_string()) })
+            .await
+            .unwrap();
+        assert_eq!(value, "v1");
+        assert_eq!(cache.circuit_state(), CircuitState::Closed);
+    }
+
+    #[tokio::test]
+    #[tracing_test::traced_test]
+    async fn test_get_span_records_hit() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "secret-key", "secret-value".to_string(), None).await;
+        cache.get("c1", "secret-key").await;
+
+        assert!(logs_contain("cache.hit=true"));
+        assert!(logs_contain("contract_id=c1"));
+        assert!(logs_contain("cache.backend=\"moka\""));
+        assert!(!logs_contain("secret-key"));
+        assert!(!logs_contain("secret-value"));
+    }
+
+    #[tokio::test]
+    async fn test_get_with_refresh_coalesces_loads() {
+        let config = CacheConfig::builder()
+            .ttl(Duration::from_millis(300))
+            .refresh_ahead(Duration::from_millis(200))
+            .build();
+        let cache = Arc::new(CacheLayer::new(config));
+        let loads = Arc::new(AtomicUsize::new(0));
+
+        let fetch_all = |cache: Arc<CacheLayer>, loads: Arc<AtomicUsize>| async move {
+            let mut handles = Vec::new();
+            for _ in 0..20 {
+                let cache = cache.clone();
+                let loads = loads.clone();
+                handles.push(tokio::spawn(async move {
+                    cache
+                        .get_with_refresh("c1", "k1", move || {
+                            let loads = loads.clone();
+                            async move {
+                                let n = loads.fetch_add(1, Ordering::SeqCst);
+                                tokio::time::sleep(Duration::from_millis(20)).await;
+                                Ok::<_, String>(format!("v{}", n))
+                            }
+                        })
+                        .await
+                        .unwrap()
+                }));
+            }
+            for handle in handles {
+                handle.await.unwrap();
+            }
+        };
+
+        // Concurrent misses share a single load
+        fetch_all(cache.clone(), loads.clone()).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+
+        // Inside the refresh window, hits trigger exactly one background reload
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        fetch_all(cache.clone(), loads.clone()).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v1".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_max_value_bytes_rejects_oversize() {
+        let cache = CacheLayer::new(CacheConfig::builder().max_value_bytes(8).build());
+
+        cache.put("c1", "small", "12345678".to_string(), None).await;
+        cache.put("c1", "large", "123456789".to_string(), None).await;
+
+        assert_eq!(cache.get("c1", "small").await.0, Some("12345678".to_string()));
+        assert_eq!(cache.get("c1", "large").await.0, None);
+        assert!(!cache.put_if_absent("c1", "large", "x".repeat(64), None).await);
+        assert_eq!(cache.metrics().snapshot().rejected_oversize, 2);
+    }
+
+    #[tokio::test]
+    async fn test_keys_for_contract() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        for key in ["balance", "admin", "counter"] {
+            cache.put("c1", key, "v".to_string(), None).await;
+        }
+        cache.put("c2", "other", "v".to_string(), None).await;
+
+        assert_eq!(cache.keys_for_contract("c1").await, vec!["admin", "balance", "counter"]);
+        assert!(cache.keys_for_contract("c3").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_hashed_long_keys() {
+        let cache = CacheLayer::new(CacheConfig::builder().hash_keys_longer_than(64).build());
+        let long_a = format!("{}a", "x".repeat(4096));
+        let long_b = format!("{}b", "x".repeat(4096));
+
+        cache.put("c1", &long_a, "va".to_string(), None).await;
+        cache.put("c1", &long_b, "vb".to_string(), None).await;
+        cache.put("c1", "short", "vs".to_string(), None).await;
+
+        assert_eq!(cache.get("c1", &long_a).await.0, Some("va".to_string()));
+        assert_eq!(cache.get("c1", &long_b).await.0, Some("vb".to_string()));
+        assert_eq!(cache.get("c1", "short").await.0, Some("vs".to_string()));
+        assert!(cache.config().state_key("c1", &long_a).len() < 64);
+
+        let mut expected = vec![long_a.clone(), long_b, "short".to_string()];
+        expected.sort();
+        assert_eq!(cache.keys_for_contract("c1").await, expected);
+
+        assert_eq!(cache.invalidate("c1", &long_a).await, 1);
+        assert_eq!(cache.get("c1", &long_a).await.0, None);
+    }
+
+    #[tokio::test]
+    async fn test_digest_strings_do_not_alias_hashed_keys() {
+        let cache = CacheLayer::new(CacheConfig::builder().hash_keys_longer_than(64).build());
+        let long = "x".repeat(4096);
+        cache.put("c1", &long, "long".to_string(), None).await;
+
+        let cache_key = cache.config().state_key("c1", &long);
+        let digest = cache_key.rsplit(':').next().unwrap().to_string();
+        assert!(digest.starts_with('#') && digest.len() <= 64);
+        assert_eq!(cache.get("c1", &digest).await, (None, false));
+        assert_ne!(cache.config().state_key("c1", &digest), cache_key);
+
+        cache.put("c1", &digest, "literal".to_string(), None).await;
+        assert_eq!(cache.get("c1", &digest).await.0, Some("literal".to_string()));
+        assert_eq!(cache.get("c1", &long).await.0, Some("long".to_string()));
+
+        // Even an entry planted in the digest's slot is only served for its own key
+        let planted = StateEntry::new("c1", CachedValue::Plain("planted".to_string()), Duration::from_secs(60));
+        cache.state_cache.insert(cache_key, planted).await;
+        assert_eq!(cache.get("c1", &long).await, (None, false));
+    }
+
+    #[tokio::test]
+    async fn test_touch_extends_ttl() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), Some(Duration::from_millis(100))).await;
+
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert_eq!(cache.touch("c1", "k1").await, Some("v1".to_string()));
+        }
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        assert_eq!(cache.touch("c1", "k1").await, None);
+    }
+
+    #[tokio::test]
+    async fn test_touch_is_not_reported_as_a_removal() {
+        let cache = CacheLayer::new(CacheConfig::builder().sliding_ttl(true).build());
+        let causes = Arc::new(Mutex::new(Vec::new()));
+        let seen = causes.clone();
+        cache.on_removal(move |_, _, cause| seen.lock().unwrap().push(cause));
+
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        assert!(cache.get("c1", "k1").await.1);
+        assert_eq!(cache.touch("c1", "k1").await, Some("v1".to_string()));
+        cache.put("c1", "k1", "v2".to_string(), None).await;
+        cache.state_cache.run_pending_tasks().await;
+
+        assert_eq!(*causes.lock().unwrap(), vec![RemovalCause::Replaced]);
+        assert!(cache.touching.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_frozen_touch_keeps_the_original_ttl() {
+        let cache = CacheLayer::new(CacheConfig::builder().sliding_ttl(true).build());
+        cache.put("c1", "k1", "v1".to_string(), Some(Duration::from_millis(100))).await;
+        cache.set_frozen(true);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(cache.get("c1", "k1").await.1);
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(!cache.get("c1", "k1").await.1);
+    }
+
+    #[tokio::test]
+    async fn test_sliding_ttl_get() {
+        let cache = CacheLayer::new(CacheConfig::builder().sliding_ttl(true).build());
+        cache.put("c1", "k1", "v1".to_string(), Some(Duration::from_millis(100))).await;
+
+        for _ in 0..5 {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            assert!(cache.get("c1", "k1").await.1);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_compare_and_set() {
+        let cache = CacheLayer::new(CacheConfig::default());
+
+        assert!(cache.compare_and_set("c1", "k1", None, "v1".to_string()).await);
+        assert!(!cache.compare_and_set("c1", "k1", None, "v2".to_string()).await);
+        assert!(!cache.compare_and_set("c1", "k1", Some("stale"), "v2".to_string()).await);
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v1".to_string()));
+
+        assert!(cache.compare_and_set("c1", "k1", Some("v1"), "v2".to_string()).await);
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v2".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_run_pending_applies_evictions() {
+        let config = CacheConfig {
+            max_capacity: 200,
+            ..Default::default()
+        };
+        let cache = CacheLayer::new(config);
+        for i in 0..50 {
+            cache.put("c1", &format!("k{}", i), "v".repeat(16), None).await;
+        }
+
+        cache.run_pending().await;
+        assert!(cache.estimated_size_bytes() <= 200);
+        assert!(cache.entry_count() < 50);
+        assert!(cache.metrics().eviction_count() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_hit_rate_since_tracks_warmup() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        for i in 0..10 {
+            cache.get("c1", &format!("k{}", i)).await;
+        }
+        cache.metrics().sample();
+        assert_eq!(cache.metrics().hit_rate_since(Duration::from_millis(50)), 0.0);
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        for _ in 0..10 {
+            cache.get("c1", "k1").await;
+        }
+
+        let metrics = cache.metrics();
+        assert_eq!(metrics.hit_rate_since(Duration::from_millis(50)), 1.0);
+        assert_eq!(metrics.hit_rate(), 0.5);
+        assert_eq!(metrics.snapshot().recent_hit_rate, 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_tag() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put_tagged("c1", "k1", "v1".to_string(), &["job-7"], None).await;
+        cache.put_tagged("c2", "k2", "v2".to_string(), &["job-7", "job-8"], None).await;
+        cache.put_tagged("c2", "k3", "v3".to_string(), &["job-8"], None).await;
+
+        assert_eq!(cache.invalidate_tag("job-7").await, 2);
+        assert!(!cache.get("c1", "k1").await.1);
+        assert!(!cache.get("c2", "k2").await.1);
+        assert!(cache.get("c2", "k3").await.1);
+        assert_eq!(cache.invalidate_tag("job-7").await, 0);
+
+        cache.prune_tags();
+        assert_eq!(cache.tags.read().unwrap()["job-8"].len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_detailed() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "present", "v1".to_string(), None).await;
+        cache.put_negative("c1", "absent", None).await;
+
+        assert_eq!(
+            cache.get_detailed("c1", "present").await,
+            CacheLookup::Hit("v1".to_string())
+        );
+        assert_eq!(cache.get_detailed("c1", "absent").await, CacheLookup::NegativeHit);
+        assert_eq!(cache.get_detailed("c1", "unknown").await, CacheLookup::Miss);
+
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.hits, snapshot.misses), (2, 1));
+    }
+
+    #[tokio::test]
+    async fn test_shadow_mode_records_hits_but_serves_misses() {
+        let cache = CacheLayer::new(CacheConfig::builder().shadow_mode(true).build());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+
+        assert_eq!(cache.get("c1", "k1").await, (None, false));
+        assert_eq!(cache.get("c1", "k2").await, (None, false));
+
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.hits, snapshot.misses), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_shadow_mode_loads_on_every_read_path() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::builder().shadow_mode(true).build()));
+        cache.put("c1", "k1", "cached".to_string(), None).await;
+
+        assert_eq!(cache.get_or_load("c1", "k1", || async { "v1".to_string() }).await, "v1");
+        let loaded = cache.try_get_or_load("c1", "k1", || async { Ok::<_, String>("v2".to_string()) });
+        assert_eq!(loaded.await.unwrap(), "v2");
+        let loaded = cache.get_with_refresh("c1", "k1", || async { Ok::<_, String>("v3".to_string()) });
+        assert_eq!(loaded.await.unwrap(), "v3");
+        let loaded = cache.get_stale_while_revalidate("c1", "k1", || async { "v4".to_string() });
+        assert_eq!(loaded.await, "v4");
+        assert!(cache.get_with_meta("c1", "k1").await.is_none());
+        assert!(cache.touch("c1", "k1").await.is_none());
+
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.hits, snapshot.misses), (6, 0));
+    }
+
+    #[tokio::test]
+    async fn test_is_underperforming() {
+        let config = CacheConfig::builder()
+            .hit_rate_floor(0.5, Duration::from_secs(60), 20)
+            .build();
+        let cache = CacheLayer::new(config);
+        cache.put("c1", "hot", "v".to_string(), None).await;
+
+        for i in 0..10 {
+            cache.get("c1", &format!("cold{}", i)).await;
+        }
+        assert!(!cache.is_underperforming(), "below the minimum sample count");
+
+        for i in 10..20 {
+            cache.get("c1", &format!("cold{}", i)).await;
+        }
+        assert!(cache.is_underperforming());
+
+        for _ in 0..30 {
+            cache.get("c1", "hot").await;
+        }
+        assert!(!cache.is_underperforming());
+    }
+
+    #[test]
+    fn test_validate_rejects_out_of_range_hit_rate_floor() {
+        let config = CacheConfig::builder()
+            .hit_rate_floor(1.5, Duration::from_secs(60), 10)
+            .build();
+        assert_eq!(config.validate(), Err(CacheConfigError::InvalidHitRateFloor(1.5)));
+    }
+
+    #[tokio::test]
+    async fn test_invalidation_webhook_retries_until_delivered() {
+        use axum::{http::StatusCode, routing::post, Json, Router};
+
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/hook",
+            post(move |Json(notice): Json<InvalidationNotice>| {
+                let attempts = attempts.clone();
+                let tx = tx.clone();
+                async move {
+                    // Fail the first delivery so the retry path is exercised
+                    if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return StatusCode::SERVICE_UNAVAILABLE;
+                    }
+                    tx.send(notice).unwrap();
+                    StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let cache = CacheLayer::new(CacheConfig::builder().invalidation_webhook(url).build());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        assert_eq!(cache.invalidate_contract("c1").await, 1);
+
+        let notice = tokio::time::timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("webhook delivered")
+            .unwrap();
+        assert_eq!(notice.contract_id, "c1");
+        assert_eq!(notice.reason, "explicit");
+        assert!(notice.ts > 0);
+    }
+
+    #[tokio::test]
+    async fn test_seeded_ttl_jitter_is_reproducible() {
+        let ttls = |seed: u64| async move {
+            let config = CacheConfig::builder()
+                .ttl(Duration::from_secs(60))
+                .ttl_jitter(Duration::from_secs(10))
+                .ttl_jitter_seed(seed)
+                .build();
+            let cache = CacheLayer::new(config);
+            let mut ttls = Vec::new();
+            for i in 0..20 {
+                let key = format!("k{}", i);
+                cache.put("c1", &key, "v".to_string(), None).await;
+                let entry = cache.state_cache.get(&cache.config().state_key("c1", &key)).await.unwrap();
+                ttls.push(entry.ttl);
+            }
+            ttls
+        };
+
+        assert_eq!(ttls(42).await, ttls(42).await);
+        assert_ne!(ttls(42).await, ttls(7).await);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_default() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+
+        assert_eq!(cache.get_or_default("c1", "k1", "fallback".to_string()).await, "v1");
+        assert_eq!(cache.get_or_default("c1", "k2", "fallback".to_string()).await, "fallback");
+        assert!(!cache.get("c1", "k2").await.1);
+    }
+
+    #[tokio::test]
+    async fn test_max_concurrent_loads() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::builder().max_concurrent_loads(4).build()));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let mut handles = Vec::new();
+        for i in 0..32 {
+            let cache = cache.clone();
+            let in_flight = in_flight.clone();
+            let peak = peak.clone();
+            handles.push(tokio::spawn(async move {
+                cache
+                    .get_or_load("c1", &format!("k{}", i), || async move {
+                        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        peak.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(10)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        format!("v{}", i)
+                    })
+                    .await
+            }));
+        }
+        for (i, handle) in handles.into_iter().enumerate() {
+            assert_eq!(handle.await.unwrap(), format!("v{}", i));
+        }
+
+        let peak = peak.load(Ordering::SeqCst);
+        assert!((1..=4).contains(&peak), "peak in-flight loads was {}", peak);
+    }
+
+    #[tokio::test]
+    async fn test_entry_age_stats() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        assert_eq!(cache.entry_age_stats().await, AgeStats::default());
+
+        cache.put("c1", "old", "v".to_string(), None).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        cache.put("c1", "new", "v".to_string(), None).await;
+
+        let stats = cache.entry_age_stats().await;
+        assert_eq!(stats.count, 2);
+        assert!(stats.oldest >= Duration::from_millis(50));
+        assert!(stats.oldest > stats.newest);
+    }
+
+    #[tokio::test]
+    async fn test_get_with_meta() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache
+            .put_with_ledger("c1", "k1", "v1".to_string(), 42, Some(Duration::from_secs(10)))
+            .await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+
+        let meta = cache.get_with_meta("c1", "k1").await.unwrap();
+        assert_eq!(meta.value, "v1");
+        assert_eq!(meta.ledger_seq, Some(42));
+        assert!(meta.age >= Duration::from_millis(20));
+        assert_eq!(meta.age + meta.ttl_remaining, Duration::from_secs(10));
+
+        assert!(cache.get_with_meta("c1", "missing").await.is_none());
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.hits, snapshot.misses), (1, 1));
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_prefix() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "balance:alice", "1".to_string(), None).await;
+        cache.put("c1", "balance:bob", "2".to_string(), None).await;
+        cache.put("c1", "meta:name", "token".to_string(), None).await;
+        cache.put("c2", "balance:alice", "3".to_string(), None).await;
+
+        assert_eq!(cache.invalidate_prefix("c1", "balance:").await, 2);
+        assert!(!cache.get("c1", "balance:alice").await.1);
+        assert!(!cache.get("c1", "balance:bob").await.1);
+        assert!(cache.get("c1", "meta:name").await.1);
+        assert!(cache.get("c2", "balance:alice").await.1);
+    }
+
+    #[tokio::test]
+    async fn test_invalidate_prefix_drops_responses_and_emits_events() {
+        let cache = CacheLayer::new(CacheConfig::builder().event_channel_capacity(16).build());
+        let response = CachedResponse {
+            status: axum::http::StatusCode::OK,
+            headers: axum::http::HeaderMap::new(),
+            body: axum::body::Bytes::from_static(b"1"),
+        };
+        cache.put("c1", "balance:alice", "1".to_string(), None).await;
+        cache.put_negative("c1", "balance:carol", None).await;
+        for key in ["balance:alice?ledger=7", "meta:name"] {
+            let url = format!("/api/contracts/c1/state/{}", key);
+            cache.put_response("c1", &url, response.clone()).await;
+        }
+
+        let mut events = Box::pin(cache.event_stream());
+        assert_eq!(cache.invalidate_prefix("c1", "balance:").await, 3);
+        assert!(cache.get_response("c1", "/api/contracts/c1/state/meta:name").await.is_some());
+
+        let mut keys = HashSet::new();
+        for _ in 0..2 {
+            let event = tokio::time::timeout(Duration::from_secs(1), events.next()).await.unwrap();
+            match event {
+                Some(CacheEvent::Invalidate { contract_id, key: Some(key) }) if contract_id == "c1" => {
+                    keys.insert(key);
+                }
+                other => panic!("unexpected event {:?}", other),
+            }
         }
+        assert_eq!(keys, HashSet::from(["balance:alice".to_string(), "balance:carol".to_string()]));
     }
-}
 
-impl CacheConfig {
-    pub fn from_env() -> Self {
-        let mut config = Self::default();
+    #[tokio::test]
+    async fn test_measure_latency_disabled() {
+        let cache = CacheLayer::new(CacheConfig::builder().measure_latency(false).build());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        cache.get("c1", "k1").await;
+        cache.get_or_load("c1", "k2", || async { "loaded".to_string() }).await;
+        cache.record_uncached_latency(Duration::from_millis(5));
 
-        if let Ok(enabled_str) = std::env::var("CACHE_ENABLED") {
-            config.enabled = enabled_str.to_lowercase() == "true";
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.hits, snapshot.misses), (1, 1));
+        assert_eq!(snapshot.cached_count, 0);
+        assert_eq!(snapshot.uncached_count, 0);
+        assert_eq!(cache.metrics().cached_latency_ns.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_cache_key_round_trip() {
+        assert_eq!(CacheKey::from_parts("", "k1"), Err(CacheKeyError::EmptyContractId));
+        assert_eq!(CacheKey::from_parts("c1", ""), Err(CacheKeyError::EmptyKey));
+
+        let key = CacheKey::from_parts("c1", "balance").unwrap();
+        assert_eq!(key.to_string(), "c1/balance");
+
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put_by_key(&key, "100".to_string(), None).await;
+        assert_eq!(cache.get_by_key(&key).await, (Some("100".to_string()), true));
+        assert_eq!(cache.get("c1", "balance").await.0, Some("100".to_string()));
+        assert_eq!(cache.get("balance", "c1").await.0, None);
+
+        assert_eq!(cache.invalidate_by_key(&key).await, 1);
+        assert_eq!(cache.get_by_key(&key).await, (None, false));
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_dead_background_task() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        let health = cache.health();
+        assert_eq!(health.status, CacheHealthStatus::Ok);
+        assert!(health.enabled && health.backend_reachable && !health.background_task_failed);
+
+        let result = cache.spawn_watched(async { panic!("background task failed") }).await;
+        assert!(result.unwrap_err().is_panic());
+
+        let health = cache.health();
+        assert_eq!(health.status, CacheHealthStatus::Degraded);
+        assert!(health.background_task_failed);
+    }
+
+    #[tokio::test]
+    async fn test_frozen_cache_skips_writes() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+
+        cache.set_frozen(true);
+        cache.put("c1", "k1", "v2".to_string(), None).await;
+        cache.put("c1", "k2", "v2".to_string(), None).await;
+        assert!(!cache.put_if_absent("c1", "k3", "v3".to_string(), None).await);
+
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v1".to_string()));
+        assert_eq!(cache.get("c1", "k2").await, (None, false));
+        assert_eq!(cache.get("c1", "k3").await, (None, false));
+        assert_eq!(cache.metrics().snapshot().put_skipped_frozen, 3);
+
+        // Invalidation is still allowed while frozen
+        assert_eq!(cache.invalidate("c1", "k1").await, 1);
+
+        cache.set_frozen(false);
+        cache.put("c1", "k2", "v2".to_string(), None).await;
+        assert_eq!(cache.get("c1", "k2").await.0, Some("v2".to_string()));
+    }
+
+    mod key_encoding {
+        use super::*;
+        use proptest::prelude::*;
+
+        fn part() -> impl Strategy<Value = String> {
+            prop_oneof![any::<String>(), "[ab:#0-9]{0,6}", Just(String::new())]
         }
 
-        if let Ok(capacity_str) = std::env::var("CACHE_MAX_CAPACITY") {
-            if let Ok(capacity) = capacity_str.parse::<u64>() {
-                config.max_capacity = capacity;
-            } else {
-                // Support parsing like "10 GB" by just falling back to 10000 limit for elements if not
+        fn separator() -> impl Strategy<Value = String> {
+            prop_oneof![Just(":".to_string()), Just("::".to_string()), "[^0-9][^\\n]{0,2}"]
+        }
+
+        proptest! {
+            #[test]
+            fn state_key_round_trips(
+                prefix in proptest::option::of("[a-z:]{0,5}"),
+                sep in separator(),
+                ns in part(),
+                key in part(),
+            ) {
+                let cache_key = compose_state_key(prefix.as_deref(), &sep, &ns, &key);
+                prop_assert_eq!(
+                    decompose_state_key(prefix.as_deref(), &sep, &cache_key),
+                    Some((ns.as_str(), key.as_str()))
+                );
+            }
+
+            #[test]
+            fn separators_the_layout_cannot_parse_are_rejected(sep in "[0-9][^\\n]{0,2}") {
+                prop_assert_eq!(
+                    CacheConfig::builder().key_separator(&sep).build().validate(),
+                    Err(CacheConfigError::DigitLeadingKeySeparator)
+                );
+            }
+
+            #[test]
+            fn distinct_pairs_get_distinct_state_keys(
+                sep in separator(),
+                a in (part(), part()),
+                b in (part(), part()),
+            ) {
+                prop_assume!(a != b);
+                prop_assert_ne!(
+                    compose_state_key(None, &sep, &a.0, &a.1),
+                    compose_state_key(None, &sep, &b.0, &b.1)
+                );
             }
         }
+    }
 
-        tracing::info!(
-            "Cache config loaded: enabled={}, capacity={}",
-            config.enabled,
-            config.max_capacity
-        );
+    #[test]
+    fn test_latency_ewma_tracks_step_change() {
+        let metrics = CacheMetrics::with_ewma_alpha(0.2);
+        for _ in 0..100 {
+            metrics.record_cached_latency(Duration::from_micros(100));
+        }
+        assert!((metrics.ewma_cached_latency() - 100.0).abs() < 1e-6);
 
-        config
+        for _ in 0..20 {
+            metrics.record_cached_latency(Duration::from_micros(1_000));
+        }
+        let ewma = metrics.ewma_cached_latency();
+        let mean = metrics.avg_cached_latency();
+        assert!(ewma > 950.0, "ewma {}", ewma);
+        assert!(mean < 300.0, "mean {}", mean);
+        assert_eq!(metrics.ewma_uncached_latency(), 0.0);
     }
-}
 
-pub struct CacheLayer {
-    pub abi_cache: MokaCache<String, String>,
-    pub verification_cache: MokaCache<String, String>,
-    config: CacheConfig,
-}
+    #[test]
+    fn test_validate_rejects_out_of_range_ewma_alpha() {
+        for alpha in [0.0, 1.5, f64::NAN] {
+            let config = CacheConfig::builder().latency_ewma_alpha(alpha).build();
+            assert!(matches!(config.validate(), Err(CacheConfigError::InvalidEwmaAlpha(_))));
+        }
+    }
 
-impl CacheLayer {
-    pub fn new(config: CacheConfig) -> Self {
-        // 24-hour TTL for ABI, max size configurable default 10GB but we use the config max_capacity 
-        let abi_cache = MokaCache::builder()
-            .max_capacity(config.max_capacity)
-            .weigher(|_k, v: &String| -> u32 {
-                v.len().try_into().unwrap_or(u32::MAX)
-            })
-            .time_to_live(Duration::from_secs(24 * 3600))
-            .build();
+    #[tokio::test]
+    async fn test_clones_share_entries_and_metrics() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        let clone = cache.clone();
 
-        // 7-day TTL for verification result cache, keyed by bytecode_hash
-        let verification_cache = MokaCache::builder()
-            .max_capacity(config.max_capacity)
-            .weigher(|_k, v: &String| -> u32 {
-                v.len().try_into().unwrap_or(u32::MAX)
-            })
-            .time_to_live(Duration::from_secs(7 * 24 * 3600))
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        assert_eq!(clone.get("c1", "k1").await.0, Some("v1".to_string()));
+        clone.invalidate("c1", "k1").await;
+        assert_eq!(cache.get("c1", "k1").await, (None, false));
+
+        assert_eq!(cache.metrics().snapshot().hits, 1);
+        assert_eq!(clone.metrics().snapshot().misses, 1);
+        clone.set_frozen(true);
+        assert!(cache.config().frozen);
+    }
+
+    #[tokio::test]
+    async fn test_serve_stale_on_error() {
+        let config = CacheConfig::builder()
+            .stale_window(Duration::from_secs(5))
+            .serve_stale_on_error(true)
             .build();
+        let cache = CacheLayer::new(config);
+        cache.put("c1", "k1", "old".to_string(), Some(Duration::from_millis(20))).await;
+        tokio::time::sleep(Duration::from_millis(40)).await;
 
-        Self { abi_cache, verification_cache, config }
+        for _ in 0..2 {
+            let value = cache
+                .try_get_or_load("c1", "k1", || async { Err::<String, _>("rpc unavailable") })
+                .await
+                .unwrap();
+            assert_eq!(value, "old");
+        }
+        assert_eq!(cache.metrics().snapshot().stale_served, 2);
+
+        // Nothing retained for this key, so the error comes through
+        let result = cache
+            .try_get_or_load("c1", "k2", || async { Err::<String, _>("rpc unavailable") })
+            .await;
+        assert!(matches!(result, Err(LoadError::Loader(_))));
+
+        let value = cache
+            .try_get_or_load("c1", "k1", || async { Ok::<_, &str>("new".to_string()) })
+            .await
+            .unwrap();
+        assert_eq!(value, "new");
     }
 
-    pub fn config(&self) -> &CacheConfig {
-        &self.config
+    #[tokio::test]
+    async fn test_put_returning_old() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        assert_eq!(cache.put_returning_old("c1", "k1", "v1".to_string(), None).await, None);
+        assert_eq!(
+            cache.put_returning_old("c1", "k1", "v2".to_string(), None).await,
+            Some("v1".to_string())
+        );
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v2".to_string()));
     }
 
-    pub async fn get_abi(&self, contract_id: &str) -> Option<String> {
-        if !self.config.enabled { return None; }
-        let result = self.abi_cache.get(contract_id).await;
-        if result.is_some() {
-            crate::metrics::ABI_CACHE_HITS.inc();
-        } else {
-            crate::metrics::ABI_CACHE_MISSES.inc();
+    #[tokio::test]
+    async fn test_checksum_mismatch_is_a_miss() {
+        let cache = CacheLayer::new(CacheConfig::builder().verify_checksums(true).build());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v1".to_string()));
+
+        // Corrupt the stored value behind the layer's back, keeping the old checksum
+        let cache_key = cache.config().state_key("c1", "k1");
+        let mut entry = cache.state_cache.get(&cache_key).await.unwrap();
+        entry.value = CachedValue::Plain("garbage".to_string());
+        cache.state_cache.insert(cache_key.clone(), entry).await;
+
+        assert_eq!(cache.get("c1", "k1").await, (None, false));
+        assert_eq!(cache.metrics().snapshot().checksum_failures, 1);
+        assert!(cache.state_cache.get(&cache_key).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_undecodable_entry_is_a_miss() {
+        let cache = CacheLayer::new(CacheConfig::builder().compression(CompressionKind::Lz4, 0).build());
+        let cache_key = cache.config().state_key("c1", "k1");
+        for kind in [CompressionKind::Lz4, CompressionKind::Zstd] {
+            let garbage = CachedValue::Compressed(kind, Arc::from(&b"\xff\xff\xff\x7fnot compressed"[..]));
+            cache.state_cache.insert(cache_key.clone(), StateEntry::new("c1", garbage, Duration::from_secs(60))).await;
+            assert_eq!(cache.get("c1", "k1").await, (None, false));
+            assert!(cache.state_cache.get(&cache_key).await.is_none());
         }
-        result
+
+        let garbage = CachedValue::Compressed(CompressionKind::Zstd, Arc::from(&b"garbage"[..]));
+        cache.state_cache.insert(cache_key.clone(), StateEntry::new("c1", garbage, Duration::from_secs(60))).await;
+        assert_eq!(cache.get_or_load("c1", "k1", || async { "v1".to_string() }).await, "v1");
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v1".to_string()));
+        assert_eq!(cache.metrics().snapshot().checksum_failures, 3);
     }
 
-    pub async fn put_abi(&self, contract_id: &str, abi: String) {
-        if !self.config.enabled { return; }
-        self.abi_cache.insert(contract_id.to_string(), abi).await;
+    #[tokio::test]
+    async fn test_load_snapshot_skips_corrupt_entries() {
+        let path = std::env::temp_dir().join(format!("cache-checksum-{}.json", std::process::id()));
+        let cache = CacheLayer::new(CacheConfig::builder().verify_checksums(true).build());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        cache.put("c1", "k2", "v2".to_string(), None).await;
+        cache.save_snapshot(&path).await.unwrap();
+
+        let tampered = std::fs::read_to_string(&path).unwrap().replace("\"v1\"", "\"vX\"");
+        std::fs::write(&path, tampered).unwrap();
+
+        let restored = CacheLayer::new(CacheConfig::default());
+        assert_eq!(restored.load_snapshot(&path).await.unwrap(), 1);
+        assert_eq!(restored.metrics().snapshot().checksum_failures, 1);
+        assert_eq!(restored.get("c1", "k1").await.0, None);
+        assert_eq!(restored.get("c1", "k2").await.0, Some("v2".to_string()));
+        std::fs::remove_file(&path).ok();
     }
 
-    pub async fn invalidate_abi(&self, contract_id: &str) {
-        if !self.config.enabled { return; }
-        self.abi_cache.invalidate(contract_id).await;
+    #[tokio::test]
+    async fn test_event_stream_reports_activity() {
+        let cache = CacheLayer::new(CacheConfig::builder().event_channel_capacity(16).build());
+        let mut events = Box::pin(cache.event_stream());
+
+        cache.get("c1", "k1").await;
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        cache.get("c1", "k1").await;
+        cache.invalidate("c1", "k1").await;
+        cache.invalidate_contract("c1").await;
+
+        let (contract_id, key) = ("c1".to_string(), "k1".to_string());
+        let expected = vec![
+            CacheEvent::Miss { contract_id: contract_id.clone(), key: key.clone() },
+            CacheEvent::Put { contract_id: contract_id.clone(), key: key.clone() },
+            CacheEvent::Hit { contract_id: contract_id.clone(), key: key.clone() },
+            CacheEvent::Invalidate { contract_id: contract_id.clone(), key: Some(key) },
+            CacheEvent::Invalidate { contract_id, key: None },
+        ];
+        for want in expected {
+            let got = tokio::time::timeout(Duration::from_secs(1), events.next())
+                .await
+                .expect("event delivered");
+            assert_eq!(got, Some(want));
+        }
+
+        // Without a capacity the stream ends straight away
+        let disabled = CacheLayer::new(CacheConfig::default());
+        assert_eq!(Box::pin(disabled.event_stream()).next().await, None);
     }
 
-    pub async fn get_verification(&self, bytecode_hash: &str) -> Option<String> {
-        if !self.config.enabled { return None; }
-        let result = self.verification_cache.get(bytecode_hash).await;
-        if result.is_some() {
-            crate::metrics::VERIFICATION_CACHE_HITS.inc();
-        } else {
-            crate::metrics::VERIFICATION_CACHE_MISSES.inc();
+    #[tokio::test]
+    async fn test_auto_tune_lengthens_ttl_under_misses() {
+        let max_ttl = Duration::from_secs(4);
+        let config = CacheConfig::builder()
+            .ttl(Duration::from_secs(1))
+            .auto_tune(AutoTuneConfig {
+                target_hit_rate: 0.9,
+                min_ttl: Duration::from_secs(1),
+                max_ttl,
+                adjust_interval: Duration::from_millis(10),
+            })
+            .build();
+        let cache = Arc::new(CacheLayer::new(config));
+        let handle = cache.clone().spawn_auto_tune().expect("auto-tune enabled");
+
+        for round in 0..40 {
+            for i in 0..20 {
+                cache.get("c1", &format!("k{}-{}", round, i)).await;
+            }
+            tokio::time::sleep(Duration::from_millis(5)).await;
         }
-        result
+
+        let ttl = cache.config().global_ttl;
+        assert!(ttl > Duration::from_secs(1), "ttl {:?}", ttl);
+        assert!(ttl <= max_ttl, "ttl {:?}", ttl);
+
+        cache.shutdown().await;
+        handle.await.unwrap();
     }
 
-    pub async fn put_verification(&self, bytecode_hash: &str, result: String) {
-        if !self.config.enabled { return; }
-        self.verification_cache.insert(bytecode_hash.to_string(), result).await;
+    #[test]
+    fn test_validate_rejects_inverted_auto_tune_bounds() {
+        let config = CacheConfig::builder()
+            .auto_tune(AutoTuneConfig {
+                min_ttl: Duration::from_secs(600),
+                max_ttl: Duration::from_secs(60),
+                ..Default::default()
+            })
+            .build();
+        assert!(matches!(config.validate(), Err(CacheConfigError::InvalidAutoTune(_))));
     }
 
-    pub async fn invalidate_verification(&self, bytecode_hash: &str) {
-        if !self.config.enabled { return; }
-        self.verification_cache.invalidate(bytecode_hash).await;
+    #[tokio::test]
+    async fn test_alias_reads_canonical_entries() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("usdc", "k0", "pre-alias".to_string(), None).await;
+        cache.register_alias("usdc", "CA123").await;
+        assert_eq!(cache.get("usdc", "k0").await, (None, false));
+
+        cache.put("CA123", "k1", "v1".to_string(), None).await;
+        assert_eq!(cache.get("usdc", "k1").await, (Some("v1".to_string()), true));
+
+        cache.put("usdc", "k2", "v2".to_string(), None).await;
+        assert_eq!(cache.get("CA123", "k2").await.0, Some("v2".to_string()));
+        assert_eq!(cache.contract_metrics("usdc"), cache.contract_metrics("CA123"));
+
+        cache.invalidate_contract("CA123").await;
+        assert_eq!(cache.get("usdc", "k1").await, (None, false));
+        assert_eq!(cache.get("usdc", "k2").await, (None, false));
     }
 
-    // Generic get method to prevent old usages from throwing compile errors during transition
-    pub async fn get(&self, _ns: &str, _key: &str) -> (Option<String>, bool) {
-        (None, false)
+    #[tokio::test]
+    async fn test_alias_of_a_renamed_canonical_follows_it() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.register_alias("usdc", "CA123").await;
+        cache.register_alias("CA123", "CB456").await;
+
+        cache.put("CB456", "k1", "v1".to_string(), None).await;
+        assert_eq!(cache.get("usdc", "k1").await.0, Some("v1".to_string()));
+        assert_eq!(cache.get("CA123", "k1").await.0, Some("v1".to_string()));
+
+        // Pointing the final canonical back at an alias is a no-op, not a cycle
+        cache.register_alias("CB456", "usdc").await;
+        assert_eq!(cache.get("usdc", "k1").await.0, Some("v1".to_string()));
     }
 
-    pub async fn put(&self, _ns: &str, _key: &str, _value: String, _ttl: Option<Duration>) {}
-    pub async fn invalidate(&self, _ns: &str, _key: &str) {}
+    #[tokio::test]
+    async fn test_drain_into_preserves_entries_and_ttls() {
+        let source = CacheLayer::new(CacheConfig::builder().capacity(64 * 1024).build());
+        let target = CacheLayer::new(CacheConfig::builder().capacity(1024 * 1024).build());
+        source.put("c1", "k1", "v1".to_string(), Some(Duration::from_secs(30))).await;
+        source.put("c1", "k2", "v2".to_string(), Some(Duration::from_secs(600))).await;
+        source.put_with_ledger("c2", "k1", "v3".to_string(), 42, None).await;
 
-    /// Starts an asynchronous startup warmup task querying the top 100 contracts
-    pub fn warm_up(self: Arc<Self>, pool: PgPool) {
-        if !self.config.enabled { return; }
-        tokio::spawn(async move {
-            tracing::info!("Starting startup cache warmup...");
-            // Query top 100 contracts by query frequency from contract_interactions or just get contracts
-            let top_contracts: Vec<(String, Option<String>)> = sqlx::query_as(
-                r#"
-                SELECT c.contract_id, c.wasm_hash
-                FROM contracts c
-                LEFT JOIN contract_interactions ci ON c.id = ci.contract_id
-                GROUP BY c.id
-                ORDER BY COUNT(ci.id) DESC
-                LIMIT 100
-                "#
-            )
-            .fetch_all(&pool)
-            .await
-            .unwrap_or_default();
+        assert_eq!(source.drain_into(&target, true).await, 3);
 
-            for (contract_id, wasm_hash) in top_contracts {
-                if let Ok(Some(abi)) = sqlx::query_scalar::<_, serde_json::Value>(
-                    "SELECT abi FROM contract_abis WHERE contract_id = $1 ORDER BY created_at DESC LIMIT 1"
-                )
-                .bind(&contract_id)
-                .fetch_optional(&pool).await {
-                    self.abi_cache.insert(contract_id.clone(), abi.to_string()).await;
-                }
+        assert_eq!(target.get("c1", "k1").await.0, Some("v1".to_string()));
+        assert_eq!(target.get("c1", "k2").await.0, Some("v2".to_string()));
+        assert_eq!(target.get("c2", "k1").await.0, Some("v3".to_string()));
+        let ttl = target.ttl_remaining("c1", "k1").await.unwrap();
+        assert!(ttl <= Duration::from_secs(30) && ttl > Duration::from_secs(29), "{:?}", ttl);
+        let ttl = target.ttl_remaining("c1", "k2").await.unwrap();
+        assert!(ttl > Duration::from_secs(599), "{:?}", ttl);
+        assert_eq!(target.invalidate_below_ledger("c2", 43).await, 1);
 
-                if let Some(w_hash) = wasm_hash {
-                    if let Ok(Some(ver_res)) = sqlx::query_scalar::<_, String>(
-                        "SELECT status::text FROM formal_verification_results LIMIT 1" // fallback fake
-                    ).fetch_optional(&pool).await {
-                        self.verification_cache.insert(w_hash.clone(), ver_res).await;
-                    }
-                }
-            }
-            tracing::info!("Completed startup cache warmup.");
+        source.run_pending().await;
+        assert_eq!(source.get("c1", "k1").await, (None, false));
+        assert_eq!(source.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_operation_timeout_abandons_blocked_writes() {
+        let cache = CacheLayer::new(
+            CacheConfig::builder().operation_timeout(Duration::from_millis(20)).build(),
+        );
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        let cache_key = cache.config().state_key("c1", "k1");
+
+        // Holds the key's compute lock the way a hung backend call would
+        let (locked_tx, locked_rx) = tokio::sync::oneshot::channel();
+        let blocker = cache.state_cache.clone();
+        let hung = tokio::spawn(async move {
+            blocker
+                .entry(cache_key)
+                .and_compute_with(|_| async move {
+                    let _ = locked_tx.send(());
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                    Op::Nop
+                })
+                .await;
         });
+        locked_rx.await.unwrap();
+
+        let started = Instant::now();
+        assert!(!cache.compare_and_set("c1", "k1", Some("v1"), "v2".to_string()).await);
+        assert!(!cache.put_if_absent("c1", "k1", "v3".to_string(), None).await);
+        assert!(started.elapsed() < Duration::from_secs(1));
+        assert_eq!(cache.metrics().snapshot().timeouts, 2);
+        hung.abort();
+        assert_eq!(cache.get("c1", "k1").await.0, Some("v1".to_string()));
+
+        assert_eq!(
+            CacheConfig::builder().operation_timeout(Duration::ZERO).build().validate(),
+            Err(CacheConfigError::ZeroOperationTimeout)
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_operation_timeout_is_configured_in_millis() {
+        let config: CacheConfig = serde_json::from_str(r#"{"operation_timeout_ms": 250}"#).unwrap();
+        assert_eq!(config.operation_timeout, Some(Duration::from_millis(250)));
+        let config: CacheConfig = serde_json::from_str(r#"{"operation_timeout_ms": "2s"}"#).unwrap();
+        assert_eq!(config.operation_timeout, Some(Duration::from_secs(2)));
+        assert_eq!(serde_json::to_value(&config).unwrap()["operation_timeout_ms"], 2000);
+    }
 
     #[tokio::test]
-    async fn test_abi_cache() {
-        let config = CacheConfig {
-            enabled: true,
-            max_capacity: 100,
+    async fn test_response_cache_invalidation() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        let response = |body: &'static str| CachedResponse {
+            status: axum::http::StatusCode::OK,
+            headers: axum::http::HeaderMap::new(),
+            body: axum::body::Bytes::from_static(body.as_bytes()),
         };
-        let cache = CacheLayer::new(config);
+        cache.put_response("c1", "/state/k", response("a")).await;
+        cache.put_response("c1", "/state/k?ledger=2", response("b")).await;
+        cache.put_response("c1", "/state/kk", response("c")).await;
+        cache.put_response("c2", "/state/k", response("d")).await;
 
-        cache.put_abi("contract_1", "abi_json_1".to_string()).await;
+        assert_eq!(cache.get_response("c1", "/state/k?ledger=2").await.unwrap().body, "b");
+        assert!(cache.get("c1", "/state/k").await.0.is_none());
 
-        let val = cache.get_abi("contract_1").await;
-        assert_eq!(val, Some("abi_json_1".to_string()));
+        assert_eq!(cache.invalidate_responses("c1", "/state/k").await, 2);
+        assert!(cache.get_response("c1", "/state/k").await.is_none());
+        assert!(cache.get_response("c1", "/state/kk").await.is_some());
 
-        cache.invalidate_abi("contract_1").await;
-        
-        let val2 = cache.get_abi("contract_1").await;
-        assert!(val2.is_none());
+        assert_eq!(cache.invalidate_contract("c2").await, 1);
+        assert!(cache.get_response("c2", "/state/k").await.is_none());
+    }
+
+    #[test]
+    fn test_layers_report_backend_and_policy() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        assert_eq!((cache.backend_name(), cache.eviction_policy()), ("moka", "tiny-lfu"));
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.backend, snapshot.eviction_policy), ("moka", "tiny-lfu"));
+
+        let typed = TypedCacheLayer::<Vec<u8>>::new(CacheConfig::default());
+        let snapshot = typed.metrics().snapshot();
+        assert_eq!((snapshot.backend, snapshot.eviction_policy), ("moka", "tiny-lfu"));
     }
 
     #[tokio::test]
-    async fn test_verification_cache() {
-        let config = CacheConfig {
-            enabled: true,
-            max_capacity: 100,
+    async fn test_response_lookups_leave_state_metrics_alone() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        let response = CachedResponse {
+            status: axum::http::StatusCode::OK,
+            headers: axum::http::HeaderMap::new(),
+            body: axum::body::Bytes::from_static(b"a"),
         };
-        let cache = CacheLayer::new(config);
+        cache.put_response("c1", "/state/k", response).await;
+        assert!(cache.get_response("c1", "/state/k").await.is_some());
+        assert!(cache.get_response("c1", "/state/missing").await.is_none());
 
-        cache.put_verification("hash_1", "result_1".to_string()).await;
-        
-        let val = cache.get_verification("hash_1").await;
-        assert_eq!(val, Some("result_1".to_string()));
-        
-        cache.invalidate_verification("hash_1").await;
-        
-        let val2 = cache.get_verification("hash_1").await;
-        assert!(val2.is_none());
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!((snapshot.hits, snapshot.misses), (0, 0));
+        assert!(cache.contract_metrics("c1").is_none());
     }
 
     #[tokio::test]
-    async fn test_disabled_cache() {
-        let config = CacheConfig {
-            enabled: false,
-            max_capacity: 100,
-        };
-        let cache = CacheLayer::new(config);
+    async fn test_contract_counters_are_bounded_and_pruned() {
+        let cache = CacheLayer::new(CacheConfig::default());
+        cache.put("c1", "k1", "v1".to_string(), None).await;
+        cache.get("c1", "k1").await;
+        for i in 0..MAX_TRACKED_CONTRACTS + 10 {
+            cache.get(&format!("random{}", i), "x").await;
+        }
+        assert_eq!(cache.contract_counters.read().unwrap().len(), MAX_TRACKED_CONTRACTS);
 
-        cache.put_abi("c1", "v1".to_string()).await;
-        let val = cache.get_abi("c1").await;
-        assert!(val.is_none());
+        cache.run_pending().await;
+        cache.prune_contract_counters();
+        assert_eq!(cache.contract_counters.read().unwrap().len(), 1);
+        assert_eq!(cache.contract_metrics("c1").unwrap().hits, 1);
+        assert!(cache.contract_metrics("random0").is_none());
+    }
 
-        cache.put_verification("h1", "v1".to_string()).await;
-        let val2 = cache.get_verification("h1").await;
-        assert!(val2.is_none());
+    #[tokio::test]
+    async fn test_invalidate_tag_covers_negatives_and_emits_events() {
+        let cache = CacheLayer::new(
+            CacheConfig::builder().event_channel_capacity(16).max_value_bytes(8).build(),
+        );
+        cache.put_tagged("c1", "k1", "v1".to_string(), &["job-7"], None).await;
+        cache.put_negative("c1", "k1", None).await;
+        cache.put_tagged("c1", "big", "x".repeat(64), &["job-9"], None).await;
+        assert!(!cache.tags.read().unwrap().contains_key("job-9"));
+
+        let mut events = Box::pin(cache.event_stream());
+        assert_eq!(cache.invalidate_tag("job-7").await, 1);
+        assert_eq!(cache.get_detailed("c1", "k1").await, CacheLookup::Miss);
+        let got = tokio::time::timeout(Duration::from_secs(1), events.next()).await.unwrap();
+        assert_eq!(
+            got,
+            Some(CacheEvent::Invalidate { contract_id: "c1".to_string(), key: Some("k1".to_string()) })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_loaders_do_not_cache_oversize_values() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::builder().max_value_bytes(8).build()));
+        let large = "x".repeat(64);
+
+        let loaded = cache.get_or_load("c1", "a", || async { "x".repeat(64) }).await;
+        assert_eq!(loaded, large);
+        let loaded = cache
+            .try_get_or_load("c1", "b", || async { Ok::<_, String>("x".repeat(64)) })
+            .await
+            .unwrap();
+        assert_eq!(loaded, large);
+        let loaded = cache
+            .get_with_refresh("c1", "c", || async { Ok::<_, String>("x".repeat(64)) })
+            .await
+            .unwrap();
+        assert_eq!(loaded, large);
+
+        for key in ["a", "b", "c"] {
+            assert_eq!(cache.get("c1", key).await.0, None);
+        }
+        let snapshot = cache.metrics().snapshot();
+        assert_eq!(snapshot.rejected_oversize, 3);
+        assert_eq!(snapshot.loader_errors, 0);
     }
 }
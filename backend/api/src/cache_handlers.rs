@@ -0,0 +1,338 @@
+use std::time::Duration;
+
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::{
+    error::{ApiError, ApiResult},
+    state::AppState,
+};
+
+/// Largest batch `POST /cache/prime` accepts in one request
+pub const MAX_PRIME_BATCH: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct PrimeEntry {
+    pub contract_id: String,
+    pub key: String,
+    pub value: String,
+    /// Overrides the configured TTL for this entry
+    pub ttl_secs: Option<u64>,
+}
+
+/// POST /cache/prime — seed the state cache with known values, e.g. from a migration job
+pub async fn prime_cache(
+    State(state): State<AppState>,
+    Json(entries): Json<Vec<PrimeEntry>>,
+) -> ApiResult<Json<Value>> {
+    if entries.len() > MAX_PRIME_BATCH {
+        return Err(ApiError::bad_request(
+            "BatchTooLarge",
+            format!(
+                "Batch of {} entries exceeds the limit of {}",
+                entries.len(),
+                MAX_PRIME_BATCH
+            ),
+        ));
+    }
+
+    let primed = entries.len();
+    for entry in entries {
+        let ttl = entry.ttl_secs.map(Duration::from_secs);
+        state
+            .cache
+            .put(&entry.contract_id, &entry.key, entry.value, ttl)
+            .await;
+    }
+    tracing::info!(primed, "cache primed");
+    Ok(Json(json!({ "primed": primed })))
+}
+
+/// DELETE /cache/:contract_id — drop every cached entry for a contract
+pub async fn invalidate_contract_cache(
+    State(state): State<AppState>,
+    Path(contract_id): Path<String>,
+) -> Json<Value> {
+    let invalidated = state.cache.invalidate_contract(&contract_id).await;
+    tracing::info!(contract_id = %contract_id, invalidated, "cache invalidated for contract");
+    Json(json!({
+        "contract_id": contract_id,
+        "invalidated": invalidated
+    }))
+}
+
+/// DELETE /cache/:contract_id/:key — drop a single cached entry
+pub async fn invalidate_cache_key(
+    State(state): State<AppState>,
+    Path((contract_id, key)): Path<(String, String)>,
+) -> Json<Value> {
+    let invalidated = state.cache.invalidate(&contract_id, &key).await;
+    tracing::info!(contract_id = %contract_id, key = %key, invalidated, "cache key invalidated");
+    Json(json!({
+        "contract_id": contract_id,
+        "key": key,
+        "invalidated": invalidated
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::AuthManager;
+    use crate::cache::{CacheConfig, CacheLayer};
+    use crate::cache_middleware::CacheMiddleware;
+    use crate::routes;
+    use axum::body::Body;
+    use axum::http::{header::AUTHORIZATION, Request, StatusCode};
+    use axum::{routing::get, Router};
+    use ed25519_dalek::{Signer, SigningKey};
+    use prometheus::Registry;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Instant;
+    use tower::ServiceExt;
+
+    /// Secret `auth_middleware` verifies tokens against in these tests
+    const TEST_JWT_SECRET: &str = "cache-handlers-test-secret";
+
+    fn test_state() -> AppState {
+        std::env::set_var("JWT_SECRET", TEST_JWT_SECRET);
+        AppState {
+            db: sqlx::pool::PoolOptions::new()
+                .max_connections(1)
+                .connect_lazy("postgres://localhost/test")
+                .expect("lazy pool"),
+            started_at: Instant::now(),
+            cache: Arc::new(CacheLayer::new(CacheConfig::default())),
+            registry: Registry::new(),
+            is_shutting_down: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+        }
+    }
+
+    /// An admin token for `auth_middleware`, issued through the challenge flow
+    fn bearer() -> String {
+        token(TEST_JWT_SECRET, true)
+    }
+
+    fn token(secret: &str, admin: bool) -> String {
+        let key = SigningKey::from_bytes(&[3u8; 32]);
+        let address = hex::encode(key.verifying_key().as_bytes());
+        let admins = admin.then(|| address.clone());
+        let mut auth = AuthManager::new(secret.to_string()).with_admins(admins);
+        let nonce = auth.create_challenge(&address);
+        let signature = hex::encode(key.sign(nonce.as_bytes()).to_bytes());
+        let token = auth
+            .verify_and_issue_jwt(&address, &address, &signature)
+            .expect("jwt");
+        format!("Bearer {}", token)
+    }
+
+    async fn delete(state: &AppState, uri: &str) -> Value {
+        let app = routes::cache_routes().with_state(state.clone());
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("DELETE")
+                    .uri(uri)
+                    .header(AUTHORIZATION, bearer())
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&body).unwrap()
+    }
+
+    #[tokio::test]
+    async fn prime_populates_state_cache() {
+        let state = test_state();
+        let app = routes::cache_routes().with_state(state.clone());
+        let batch = json!([
+            { "contract_id": "c1", "key": "k1", "value": "v1" },
+            { "contract_id": "c2", "key": "k2", "value": "v2", "ttl_secs": 60 }
+        ]);
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/cache/prime")
+                    .header("content-type", "application/json")
+                    .header(AUTHORIZATION, bearer())
+                    .body(Body::from(batch.to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let ack: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(ack["primed"], 2);
+
+        assert_eq!(state.cache.get("c1", "k1").await, (Some("v1".to_string()), true));
+        assert_eq!(state.cache.get("c2", "k2").await, (Some("v2".to_string()), true));
+    }
+
+    #[tokio::test]
+    async fn prime_rejects_oversized_batch() {
+        let state = test_state();
+        let app = routes::cache_routes().with_state(state.clone());
+        let batch: Vec<Value> = (0..=MAX_PRIME_BATCH)
+            .map(|i| json!({ "contract_id": "c1", "key": format!("k{}", i), "value": "v" }))
+            .collect();
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/cache/prime")
+                    .header("content-type", "application/json")
+                    .header(AUTHORIZATION, bearer())
+                    .body(Body::from(Value::from(batch).to_string()))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(!state.cache.get("c1", "k0").await.1);
+    }
+
+    #[tokio::test]
+    async fn delete_contract_invalidates_all_keys() {
+        let state = test_state();
+        state.cache.put("c1", "k1", "v1".to_string(), None).await;
+        state.cache.put("c1", "k2", "v2".to_string(), None).await;
+        state.cache.put("c2", "k1", "other".to_string(), None).await;
+
+        let ack = delete(&state, "/cache/c1").await;
+        assert_eq!(ack["invalidated"], 2);
+
+        assert!(!state.cache.get("c1", "k1").await.1);
+        assert!(!state.cache.get("c1", "k2").await.1);
+        assert!(state.cache.get("c2", "k1").await.1);
+    }
+
+    #[tokio::test]
+    async fn delete_key_invalidates_single_entry() {
+        let state = test_state();
+        state.cache.put("c1", "k1", "v1".to_string(), None).await;
+        state.cache.put("c1", "k2", "v2".to_string(), None).await;
+
+        let ack = delete(&state, "/cache/c1/k1").await;
+        assert_eq!(ack["invalidated"], 1);
+        assert_eq!(ack["key"], "k1");
+
+        assert!(!state.cache.get("c1", "k1").await.1);
+        assert!(state.cache.get("c1", "k2").await.1);
+
+        let ack = delete(&state, "/cache/c1/k1").await;
+        assert_eq!(ack["invalidated"], 0);
+    }
+
+    #[tokio::test]
+    async fn delete_key_drops_cached_responses() {
+        let state = test_state();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let contract_routes = Router::new()
+            .route(
+                "/api/contracts/:id/state/:key",
+                get(move || {
+                    let calls = counter.clone();
+                    async move { calls.fetch_add(1, Ordering::SeqCst).to_string() }
+                }),
+            )
+            .layer(CacheMiddleware::new(state.cache.clone()));
+        let app = routes::cache_routes().merge(contract_routes).with_state(state.clone());
+        let get_state = || {
+            Request::builder()
+                .uri("/api/contracts/c1/state/counter")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        app.clone().oneshot(get_state()).await.unwrap();
+        let hit = app.clone().oneshot(get_state()).await.unwrap();
+        assert_eq!(hit.headers()["x-cache"], "HIT");
+
+        let ack = delete(&state, "/cache/c1/counter").await;
+        assert_eq!(ack["invalidated"], 1);
+
+        let miss = app.oneshot(get_state()).await.unwrap();
+        assert_eq!(miss.headers()["x-cache"], "MISS");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_routes_require_a_token() {
+        let state = test_state();
+        state.cache.put("c1", "k1", "v1".to_string(), None).await;
+        let app = routes::cache_routes().with_state(state.clone());
+
+        for (method, uri) in [("POST", "/cache/prime"), ("DELETE", "/cache/c1"), ("GET", "/cache/stats")] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method(method)
+                        .uri(uri)
+                        .header("content-type", "application/json")
+                        .body(Body::from("[]"))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "{} {}", method, uri);
+        }
+        assert!(state.cache.get("c1", "k1").await.1);
+    }
+
+    async fn status(state: &AppState, method: &str, uri: &str, authorization: String) -> StatusCode {
+        routes::cache_routes()
+            .with_state(state.clone())
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(uri)
+                    .header("content-type", "application/json")
+                    .header(AUTHORIZATION, authorization)
+                    .body(Body::from("[]"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn tokens_signed_with_the_old_fallback_secret_are_rejected() {
+        let state = test_state();
+        state.cache.put("c1", "k1", "v1".to_string(), None).await;
+
+        let forged = token("dev-only-secret", true);
+        assert_eq!(status(&state, "DELETE", "/cache/c1", forged).await, StatusCode::UNAUTHORIZED);
+        assert!(state.cache.get("c1", "k1").await.1);
+    }
+
+    #[tokio::test]
+    async fn write_routes_require_an_admin_token() {
+        let state = test_state();
+        state.cache.put("c1", "k1", "v1".to_string(), None).await;
+
+        let user = token(TEST_JWT_SECRET, false);
+        for (method, uri) in [("POST", "/cache/prime"), ("DELETE", "/cache/c1"), ("DELETE", "/cache/c1/k1")] {
+            let status = status(&state, method, uri, user.clone()).await;
+            assert_eq!(status, StatusCode::FORBIDDEN, "{} {}", method, uri);
+        }
+        assert!(state.cache.get("c1", "k1").await.1);
+        assert_eq!(status(&state, "POST", "/cache/prime", bearer()).await, StatusCode::OK);
+    }
+}
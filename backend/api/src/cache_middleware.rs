@@ -0,0 +1,439 @@
+use std::{
+    convert::Infallible,
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::{
+        header::{
+            AUTHORIZATION, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LANGUAGE, CONTENT_TYPE, ETAG, LAST_MODIFIED,
+            SET_COOKIE,
+        },
+        HeaderMap, HeaderName, HeaderValue, Method, StatusCode,
+    },
+    response::{IntoResponse, Response},
+};
+use tower::{Layer, Service};
+
+use crate::cache::{CacheLayer, CachedResponse};
+
+const HEADER_CACHE_STATUS: HeaderName = HeaderName::from_static("x-cache");
+
+/// Largest body buffered for caching when `max_value_bytes` is unset
+const MAX_CACHED_BODY_BYTES: usize = 1024 * 1024;
+
+/// Response headers stored with a cached body and replayed on a hit
+const REPLAYED_HEADERS: [HeaderName; 5] = [
+    CONTENT_TYPE,
+    CONTENT_ENCODING,
+    CONTENT_LANGUAGE,
+    ETAG,
+    LAST_MODIFIED,
+];
+
+/// Tower layer that serves repeated `GET /api/contracts/:id/state/:key` requests from
+/// the cache layer's response cache. Entries are namespaced by contract id and keyed
+/// by path and query, so `CacheLayer::invalidate` and `invalidate_contract` drop them
+/// along with the state they were served from. Only `200 OK` responses are cached,
+/// with their status and the `REPLAYED_HEADERS`; bodies of unknown length or over
+/// `max_value_bytes` pass through uncached, as do requests carrying `Authorization`.
+/// Successful POST, PUT, PATCH and DELETE requests drop the path's cached responses.
+#[derive(Clone)]
+pub struct CacheMiddleware {
+    cache: Arc<CacheLayer>,
+}
+
+impl CacheMiddleware {
+    pub fn new(cache: Arc<CacheLayer>) -> Self {
+        Self { cache }
+    }
+}
+
+impl<S> Layer<S> for CacheMiddleware {
+    type Service = CacheMiddlewareService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        CacheMiddlewareService {
+            inner,
+            cache: self.cache.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct CacheMiddlewareService<S> {
+    inner: S,
+    cache: Arc<CacheLayer>,
+}
+
+impl<S> Service<Request> for CacheMiddlewareService<S>
+where
+    S: Service<Request, Response = Response, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = Infallible;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, Infallible>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request) -> Self::Future {
+        // Take the service that was driven to readiness and leave a fresh clone behind
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let cache = self.cache.clone();
+
+        Box::pin(async move {
+            let path = request.uri().path().to_string();
+            let url = request
+                .uri()
+                .path_and_query()
+                .map_or_else(|| path.clone(), |url| url.as_str().to_string());
+            let Some(contract_id) = extract_state_contract_id(&path).map(str::to_string) else {
+                return inner.call(request).await;
+            };
+
+            if is_write(request.method()) {
+                // Writes to the state resource make any cached GET for it stale
+                let response = inner.call(request).await?;
+                if response.status().is_success() {
+                    cache.invalidate_responses(&contract_id, &path).await;
+                }
+                return Ok(response);
+            }
+            // Authenticated responses may be tailored to the caller, so never share them
+            if request.method() != Method::GET || request.headers().contains_key(AUTHORIZATION) {
+                return inner.call(request).await;
+            }
+
+            let no_cache = request
+                .headers()
+                .get(CACHE_CONTROL)
+                .and_then(|value| value.to_str().ok())
+                .is_some_and(|value| value.contains("no-cache"));
+
+            if !no_cache {
+                if let Some(cached) = cache.get_response(&contract_id, &url).await {
+                    return Ok(cached_response(cached));
+                }
+            }
+
+            let response = inner.call(request).await?;
+            if response.status() != StatusCode::OK || !is_shareable(response.headers()) {
+                return Ok(response);
+            }
+            let limit = cache.config().max_value_bytes.unwrap_or(MAX_CACHED_BODY_BYTES);
+            if response.body().size_hint().upper().is_none_or(|len| len > limit as u64) {
+                return Ok(response);
+            }
+
+            let (mut parts, body) = response.into_parts();
+            let bytes = match axum::body::to_bytes(body, limit).await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    // The body is partly consumed, so the original response can't be sent
+                    tracing::warn!("Failed to buffer response for caching: {}", e);
+                    return Ok(StatusCode::INTERNAL_SERVER_ERROR.into_response());
+                }
+            };
+            let mut headers = HeaderMap::new();
+            for name in REPLAYED_HEADERS {
+                for value in parts.headers.get_all(&name) {
+                    headers.append(name.clone(), value.clone());
+                }
+            }
+            let cached = CachedResponse {
+                status: parts.status,
+                headers,
+                body: bytes.clone(),
+            };
+            cache.put_response(&contract_id, &url, cached).await;
+            parts
+                .headers
+                .insert(HEADER_CACHE_STATUS, HeaderValue::from_static("MISS"));
+            Ok(Response::from_parts(parts, Body::from(bytes)))
+        })
+    }
+}
+
+fn is_write(method: &Method) -> bool {
+    matches!(*method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE)
+}
+
+fn cached_response(cached: CachedResponse) -> Response {
+    let mut response = Response::new(Body::from(cached.body));
+    *response.status_mut() = cached.status;
+    *response.headers_mut() = cached.headers;
+    response
+        .headers_mut()
+        .insert(HEADER_CACHE_STATUS, HeaderValue::from_static("HIT"));
+    response
+}
+
+/// Whether a response may be served to other clients: nothing per-user such as
+/// a cookie, and no `Cache-Control` forbidding shared caching
+fn is_shareable(headers: &HeaderMap) -> bool {
+    if headers.contains_key(SET_COOKIE) {
+        return false;
+    }
+    !headers
+        .get_all(CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .any(|value| value.contains("no-store") || value.contains("private"))
+}
+
+/// Returns the contract id for paths like `/api/contracts/{id}/state/{key}`
+fn extract_state_contract_id(path: &str) -> Option<&str> {
+    let parts: Vec<&str> = path.split('/').collect();
+    match parts.as_slice() {
+        ["", "api", "contracts", id, "state", key] if !id.is_empty() && !key.is_empty() => {
+            Some(id)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheConfig;
+    use axum::{extract::Path, routing::get, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tower::ServiceExt;
+
+    fn test_app(cache: Arc<CacheLayer>, calls: Arc<AtomicUsize>) -> Router<()> {
+        Router::new()
+            .route(
+                "/api/contracts/:id/state/:key",
+                get(move || {
+                    let calls = calls.clone();
+                    async move {
+                        let n = calls.fetch_add(1, Ordering::SeqCst);
+                        format!("{{\"version\":{}}}", n)
+                    }
+                })
+                .post(|| async { StatusCode::NO_CONTENT }),
+            )
+            .layer(CacheMiddleware::new(cache))
+    }
+
+    fn state_request(cache_control: Option<&str>) -> Request {
+        get_request("/api/contracts/c1/state/counter", cache_control)
+    }
+
+    fn get_request(uri: &str, cache_control: Option<&str>) -> Request {
+        let mut builder = Request::builder().uri(uri).method("GET");
+        if let Some(value) = cache_control {
+            builder = builder.header(CACHE_CONTROL, value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    async fn body_string(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn serves_repeated_get_from_cache() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = test_app(cache, calls.clone());
+
+        let first = app.clone().oneshot(state_request(None)).await.unwrap();
+        assert_eq!(first.headers()[&HEADER_CACHE_STATUS], "MISS");
+        let first_body = body_string(first).await;
+
+        let second = app.oneshot(state_request(None)).await.unwrap();
+        assert_eq!(second.status(), StatusCode::OK);
+        assert_eq!(second.headers()[&HEADER_CACHE_STATUS], "HIT");
+        assert_eq!(body_string(second).await, first_body);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn no_cache_header_bypasses_cache() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = test_app(cache, calls.clone());
+
+        app.clone().oneshot(state_request(None)).await.unwrap();
+        let response = app.oneshot(state_request(Some("no-cache"))).await.unwrap();
+
+        assert_eq!(response.headers()[&HEADER_CACHE_STATUS], "MISS");
+        assert_eq!(body_string(response).await, "{\"version\":1}");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn only_state_paths_are_cacheable() {
+        assert_eq!(extract_state_contract_id("/api/contracts/c1/state/k"), Some("c1"));
+        assert_eq!(extract_state_contract_id("/api/contracts/c1"), None);
+        assert_eq!(extract_state_contract_id("/api/contracts/c1/state/"), None);
+    }
+
+    #[tokio::test]
+    async fn query_string_is_part_of_the_key() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = test_app(cache, calls.clone());
+        let uri = |ledger| format!("/api/contracts/c1/state/counter?ledger={}", ledger);
+
+        let first = app.clone().oneshot(get_request(&uri(1), None)).await.unwrap();
+        let second = app.clone().oneshot(get_request(&uri(2), None)).await.unwrap();
+        assert_eq!(second.headers()[&HEADER_CACHE_STATUS], "MISS");
+        assert_ne!(body_string(first).await, body_string(second).await);
+
+        let again = app.oneshot(get_request(&uri(1), None)).await.unwrap();
+        assert_eq!(again.headers()[&HEADER_CACHE_STATUS], "HIT");
+        assert_eq!(body_string(again).await, "{\"version\":0}");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn hit_replays_status_and_headers() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let app = Router::new()
+            .route(
+                "/api/contracts/:id/state/:key",
+                get(|| async {
+                    ([(CONTENT_TYPE, "application/xdr"), (ETAG, "\"v1\"")], "AAAA")
+                }),
+            )
+            .layer(CacheMiddleware::new(cache));
+
+        app.clone().oneshot(state_request(None)).await.unwrap();
+        let hit = app.oneshot(state_request(None)).await.unwrap();
+
+        assert_eq!(hit.status(), StatusCode::OK);
+        assert_eq!(hit.headers()[&HEADER_CACHE_STATUS], "HIT");
+        assert_eq!(hit.headers()[CONTENT_TYPE], "application/xdr");
+        assert_eq!(hit.headers()[ETAG], "\"v1\"");
+        assert_eq!(body_string(hit).await, "AAAA");
+    }
+
+    #[tokio::test]
+    async fn only_successful_shareable_responses_are_cached() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let app = Router::new()
+            .route(
+                "/api/contracts/:id/state/:key",
+                get(move |Path((id, _key)): Path<(String, String)>| {
+                    let calls = counter.clone();
+                    async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        match id.as_str() {
+                            "missing" => StatusCode::NOT_FOUND.into_response(),
+                            _ => ([(CACHE_CONTROL, "private")], "mine").into_response(),
+                        }
+                    }
+                }),
+            )
+            .layer(CacheMiddleware::new(cache.clone()));
+
+        for uri in ["/api/contracts/missing/state/k", "/api/contracts/c1/state/k"] {
+            for _ in 0..2 {
+                app.clone().oneshot(get_request(uri, None)).await.unwrap();
+            }
+        }
+        assert_eq!(calls.load(Ordering::SeqCst), 4);
+        cache.run_pending().await;
+        assert_eq!(cache.response_cache.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn oversized_and_unsized_bodies_pass_through_uncached() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::builder().max_value_bytes(8).build()));
+        let app = Router::new()
+            .route(
+                "/api/contracts/:id/state/:key",
+                get(|Path((_id, key)): Path<(String, String)>| async move {
+                    match key.as_str() {
+                        "big" => Body::from("more than eight bytes"),
+                        _ => Body::from_stream(tokio_stream::iter([Ok::<_, std::io::Error>("streamed")])),
+                    }
+                }),
+            )
+            .layer(CacheMiddleware::new(cache.clone()));
+
+        for uri in ["/api/contracts/c1/state/big", "/api/contracts/c1/state/streamed"] {
+            let response = app.clone().oneshot(get_request(uri, None)).await.unwrap();
+            assert!(response.headers().get(&HEADER_CACHE_STATUS).is_none());
+            assert!(!body_string(response).await.is_empty());
+        }
+        cache.run_pending().await;
+        assert_eq!(cache.response_cache.entry_count(), 0);
+    }
+
+    #[tokio::test]
+    async fn authorized_requests_bypass_cache() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = test_app(cache, calls.clone());
+        let authorized = || {
+            Request::builder()
+                .uri("/api/contracts/c1/state/counter")
+                .header(AUTHORIZATION, "Bearer token")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        app.clone().oneshot(authorized()).await.unwrap();
+        let miss = app.clone().oneshot(state_request(None)).await.unwrap();
+        assert_eq!(miss.headers()[&HEADER_CACHE_STATUS], "MISS");
+        let response = app.oneshot(authorized()).await.unwrap();
+        assert!(response.headers().get(&HEADER_CACHE_STATUS).is_none());
+        assert_eq!(body_string(response).await, "{\"version\":2}");
+    }
+
+    #[tokio::test]
+    async fn only_write_methods_invalidate() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = test_app(cache, calls.clone());
+        let request = |method: &str| {
+            Request::builder()
+                .method(method)
+                .uri("/api/contracts/c1/state/counter")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        app.clone().oneshot(state_request(None)).await.unwrap();
+        for method in ["HEAD", "OPTIONS"] {
+            app.clone().oneshot(request(method)).await.unwrap();
+        }
+        let hit = app.clone().oneshot(state_request(None)).await.unwrap();
+        assert_eq!(hit.headers()[&HEADER_CACHE_STATUS], "HIT");
+
+        let post = app.clone().oneshot(request("POST")).await.unwrap();
+        assert!(post.status().is_success());
+        let miss = app.oneshot(state_request(None)).await.unwrap();
+        assert_eq!(miss.headers()[&HEADER_CACHE_STATUS], "MISS");
+    }
+
+    #[tokio::test]
+    async fn state_cache_values_are_never_served_as_responses() {
+        let cache = Arc::new(CacheLayer::new(CacheConfig::default()));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let app = test_app(cache.clone(), calls.clone());
+        let path = "/api/contracts/c1/state/counter";
+        cache.put("c1", path, "planted".to_string(), None).await;
+
+        let response = app.oneshot(state_request(None)).await.unwrap();
+        assert_eq!(response.headers()[&HEADER_CACHE_STATUS], "MISS");
+        assert_eq!(body_string(response).await, "{\"version\":0}");
+    }
+}
@@ -0,0 +1,164 @@
+/// Synthetic cache workloads shared by the `cache` benchmark and hit-rate tests
+use crate::cache::CacheLayer;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// How keys are picked for each operation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyDistribution {
+    /// Every key is equally likely
+    Uniform,
+    /// Key `i` is picked with probability proportional to `1 / (i + 1)^exponent`,
+    /// so a few keys take most of the traffic
+    Zipfian { exponent: f64 },
+}
+
+/// Shape of a generated workload
+#[derive(Clone, Debug)]
+pub struct WorkloadSpec {
+    pub num_keys: usize,
+    pub num_ops: usize,
+    /// Fraction of operations (0-1) that are writes
+    pub write_ratio: f64,
+    pub distribution: KeyDistribution,
+    /// Same seed, same operations
+    pub seed: u64,
+}
+
+impl Default for WorkloadSpec {
+    fn default() -> Self {
+        Self {
+            num_keys: 1_000,
+            num_ops: 10_000,
+            write_ratio: 0.1,
+            distribution: KeyDistribution::Zipfian { exponent: 1.0 },
+            seed: 42,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WorkloadOp {
+    Get(String),
+    Put(String, String),
+}
+
+/// Builds the operation sequence described by `spec`
+pub fn generate_workload(spec: &WorkloadSpec) -> Vec<WorkloadOp> {
+    let mut rng = StdRng::seed_from_u64(spec.seed);
+    let num_keys = spec.num_keys.max(1);
+
+    // Cumulative weights for zipfian picks; a binary search maps a uniform draw to a key
+    let cdf: Option<Vec<f64>> = match spec.distribution {
+        KeyDistribution::Uniform => None,
+        KeyDistribution::Zipfian { exponent } => {
+            let mut total = 0.0;
+            Some(
+                (0..num_keys)
+                    .map(|i| {
+                        total += 1.0 / ((i + 1) as f64).powf(exponent);
+                        total
+                    })
+                    .collect(),
+            )
+        }
+    };
+
+    (0..spec.num_ops)
+        .map(|i| {
+            let index = match &cdf {
+                None => rng.gen_range(0..num_keys),
+                Some(cdf) => {
+                    let draw = rng.gen::<f64>() * cdf[num_keys - 1];
+                    cdf.partition_point(|&weight| weight < draw)
+                        .min(num_keys - 1)
+                }
+            };
+            let key = format!("key_{}", index);
+            if rng.gen::<f64>() < spec.write_ratio {
+                WorkloadOp::Put(key, format!("value_{}", i))
+            } else {
+                WorkloadOp::Get(key)
+            }
+        })
+        .collect()
+}
+
+/// Replays `ops` against `cache` under namespace `ns`, loading every read miss
+/// back into the cache, and returns the read hit rate (0-1)
+pub async fn run_workload(cache: &CacheLayer, ns: &str, ops: &[WorkloadOp]) -> f64 {
+    let (mut hits, mut reads) = (0usize, 0usize);
+    for op in ops {
+        match op {
+            WorkloadOp::Get(key) => {
+                reads += 1;
+                if cache.get(ns, key).await.1 {
+                    hits += 1;
+                } else {
+                    cache.put(ns, key, format!("loaded_{}", key), None).await;
+                }
+            }
+            WorkloadOp::Put(key, value) => cache.put(ns, key, value.clone(), None).await,
+        }
+    }
+    if reads == 0 {
+        return 0.0;
+    }
+    hits as f64 / reads as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cache::CacheConfig;
+
+    #[test]
+    fn test_generate_workload_is_deterministic() {
+        let spec = WorkloadSpec {
+            num_ops: 500,
+            ..Default::default()
+        };
+        let ops = generate_workload(&spec);
+        assert_eq!(ops.len(), 500);
+        assert_eq!(ops, generate_workload(&spec));
+
+        let writes = ops
+            .iter()
+            .filter(|op| matches!(op, WorkloadOp::Put(..)))
+            .count();
+        assert!((1..150).contains(&writes), "{} writes", writes);
+    }
+
+    #[tokio::test]
+    async fn test_zipfian_workload_hits_more_than_uniform() {
+        let config = CacheConfig {
+            max_capacity: 4_000,
+            ..Default::default()
+        };
+        let spec = |distribution| WorkloadSpec {
+            num_keys: 2_000,
+            num_ops: 5_000,
+            write_ratio: 0.0,
+            distribution,
+            seed: 7,
+        };
+
+        let uniform = CacheLayer::new(config.clone());
+        let uniform_rate = run_workload(
+            &uniform,
+            "c1",
+            &generate_workload(&spec(KeyDistribution::Uniform)),
+        )
+        .await;
+        let zipfian = CacheLayer::new(config);
+        let zipfian_ops = generate_workload(&spec(KeyDistribution::Zipfian { exponent: 1.1 }));
+        let zipfian_rate = run_workload(&zipfian, "c1", &zipfian_ops).await;
+
+        assert!(
+            zipfian_rate > uniform_rate,
+            "zipfian {} <= uniform {}",
+            zipfian_rate,
+            uniform_rate
+        );
+    }
+}
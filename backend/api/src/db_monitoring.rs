@@ -41,18 +41,15 @@ pub fn spawn_db_monitoring_task(pool: PgPool, cache: Arc<CacheLayer>) {
             }
 
             // Moka Cache Metrics
-            let abi_entries = cache.abi_cache.entry_count();
-            let abi_size = cache.abi_cache.weighted_size();
-            let ver_entries = cache.verification_cache.entry_count();
-            let ver_size = cache.verification_cache.weighted_size();
-
-            metrics::CACHE_ENTRIES.set(abi_entries.saturating_add(ver_entries) as i64);
-            metrics::CACHE_SIZE_BYTES.set(abi_size.saturating_add(ver_size) as i64);
+            let total_entries = cache.entry_count();
+            metrics::CACHE_ENTRIES.set(total_entries as i64);
+            metrics::CACHE_SIZE_BYTES.set(cache.estimated_size_bytes() as i64);
+            cache.metrics().export_prometheus();
 
             tracing::debug!(
                 db_active = active_connections,
                 db_idle = idle_connections,
-                cache_entries = abi_entries + ver_entries,
+                cache_entries = total_entries,
                 "Resource monitoring update"
             );
         }
@@ -188,6 +188,7 @@ pub async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<Va
         .fetch_one(&state.db)
         .await
         .is_ok();
+    let cache = state.cache.health();
 
     if db_ok {
         tracing::info!(uptime_secs = uptime, "health check passed");
@@ -197,7 +198,8 @@ pub async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<Va
                 "status": "ok",
                 "version": "0.1.0",
                 "timestamp": now,
-                "uptime_secs": uptime
+                "uptime_secs": uptime,
+                "cache": cache
             })),
         )
     } else {
@@ -211,7 +213,8 @@ pub async fn health_check(State(state): State<AppState>) -> (StatusCode, Json<Va
                 "status": "degraded",
                 "version": "0.1.0",
                 "timestamp": now,
-                "uptime_secs": uptime
+                "uptime_secs": uptime,
+                "cache": cache
             })),
         )
     }
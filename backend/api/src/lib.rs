@@ -3,6 +3,7 @@
 pub mod backup_handlers;
 pub mod backup_routes;
 pub mod cache;
+pub mod cache_workload;
 pub mod disaster_recovery_models;
 pub mod error;
 pub mod notification_handlers;
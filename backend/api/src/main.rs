@@ -2,8 +2,12 @@
 
 mod aggregation;
 mod analytics;
+mod auth;
+mod auth_middleware;
 mod breaking_changes;
 mod cache;
+mod cache_handlers;
+mod cache_middleware;
 mod compatibility_testing_handlers;
 mod db_monitoring;
 
@@ -30,7 +34,6 @@ pub mod signing_handlers;
 mod state;
 mod type_safety;
 mod validation;
-// mod auth;
 // mod auth_handlers;
 // mod resource_handlers;
 // mod resource_tracking;
@@ -47,6 +50,7 @@ use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
+use crate::cache_middleware::CacheMiddleware;
 use crate::rate_limit::RateLimitState;
 use crate::state::AppState;
 
@@ -112,8 +116,22 @@ async fn main() -> Result<()> {
     // Warm up the cache
     state.cache.clone().warm_up(pool.clone());
 
+    // Periodically purge expired cache entries
+    state.cache.clone().spawn_maintenance();
+    state.cache.clone().spawn_auto_tune();
+    let cache = state.cache.clone();
+
     let rate_limit_state = RateLimitState::from_env();
 
+    // Cache administration is only reachable with tokens we can actually verify
+    let cache_routes = match auth::AuthManager::from_env() {
+        Ok(_) => routes::cache_routes(),
+        Err(reason) => {
+            tracing::warn!(reason, "JWT_SECRET is not set; cache admin routes are disabled");
+            Router::new()
+        }
+    };
+
     let cors = CorsLayer::new()
         .allow_origin([
             HeaderValue::from_static("http://localhost:3000"),
@@ -124,9 +142,10 @@ async fn main() -> Result<()> {
 
     // Build router
     let app = Router::new()
-        .merge(routes::contract_routes())
+        .merge(routes::contract_routes().layer(CacheMiddleware::new(state.cache.clone())))
         .merge(routes::publisher_routes())
         .merge(routes::health_routes())
+        .merge(cache_routes)
         .merge(routes::migration_routes())
         .merge(routes::compatibility_dashboard_routes())
         .merge(release_notes_routes::release_notes_routes())
@@ -196,6 +215,9 @@ async fn main() -> Result<()> {
         } => {}
     }
 
+    tracing::info!("Flushing cache...");
+    cache.shutdown().await;
+
     tracing::info!("Closing database connections...");
     pool.close().await;
     tracing::info!("Shutdown complete");
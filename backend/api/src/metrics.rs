@@ -1,7 +1,7 @@
 use once_cell::sync::Lazy;
 use prometheus::{
-    opts, Encoder, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGauge,
-    IntGaugeVec, Registry, TextEncoder,
+    opts, Encoder, Gauge, GaugeVec, HistogramOpts, HistogramVec, IntCounter, IntCounterVec,
+    IntGauge, IntGaugeVec, Registry, TextEncoder,
 };
 
 pub static REGISTRY: Lazy<Registry> = Lazy::new(Registry::new);
@@ -37,6 +37,11 @@ macro_rules! gauge_vec {
         Lazy::new(|| IntGaugeVec::new(opts!($name, $help), $labels).unwrap())
     };
 }
+macro_rules! gauge_f64 {
+    ($name:expr, $help:expr) => {
+        Lazy::new(|| Gauge::new($name, $help).unwrap())
+    };
+}
 macro_rules! gauge_f64_vec {
     ($name:expr, $help:expr, $labels:expr) => {
         Lazy::new(|| GaugeVec::new(opts!($name, $help), $labels).unwrap())
@@ -150,13 +155,24 @@ pub static DB_POOL_UTILIZATION: Lazy<GaugeVec> = gauge_f64_vec!("db_pool_utiliza
 pub static CACHE_HITS: Lazy<IntCounter> = counter!("cache_hits_total", "Cache hits");
 pub static CACHE_MISSES: Lazy<IntCounter> = counter!("cache_misses_total", "Cache misses");
 pub static CACHE_EVICTIONS: Lazy<IntCounter> = counter!("cache_evictions_total", "Cache evictions");
+pub static CACHE_LOADER_ERRORS: Lazy<IntCounter> = counter!("cache_loader_errors_total", "Failed cache loader calls");
+pub static CACHE_REJECTED_OVERSIZE: Lazy<IntCounter> = counter!("cache_rejected_oversize_total", "Values too large to cache");
+pub static CACHE_PUT_SKIPPED_FROZEN: Lazy<IntCounter> = counter!("cache_put_skipped_frozen_total", "Cache writes dropped while frozen");
+pub static CACHE_STALE_SERVED: Lazy<IntCounter> = counter!("cache_stale_served_total", "Failed cache loads answered with a stale value");
+pub static CACHE_CHECKSUM_FAILURES: Lazy<IntCounter> = counter!("cache_checksum_failures_total", "Cache entries dropped for a checksum mismatch");
+pub static CACHE_TIMEOUTS: Lazy<IntCounter> = counter!("cache_timeouts_total", "State cache operations abandoned after the operation timeout");
 pub static CACHE_SIZE_BYTES: Lazy<IntGauge> = gauge!("cache_size_bytes", "Cache size in bytes");
 pub static CACHE_ENTRIES: Lazy<IntGauge> = gauge!("cache_entries", "Number of cached entries");
+pub static CACHE_HIT_RATIO: Lazy<Gauge> = gauge_f64!("cache_hit_ratio", "State cache hit ratio (0-1)");
+pub static CACHE_AVG_CACHED_LATENCY_MICROS: Lazy<Gauge> = gauge_f64!("cache_avg_cached_latency_micros", "Average state cache hit latency in microseconds");
+pub static CACHE_AVG_UNCACHED_LATENCY_MICROS: Lazy<Gauge> = gauge_f64!("cache_avg_uncached_latency_micros", "Average uncached load latency in microseconds");
 
 pub static ABI_CACHE_HITS: Lazy<IntCounter> = counter!("abi_cache_hits_total", "ABI cache hits");
 pub static ABI_CACHE_MISSES: Lazy<IntCounter> = counter!("abi_cache_misses_total", "ABI cache misses");
 pub static VERIFICATION_CACHE_HITS: Lazy<IntCounter> = counter!("verification_cache_hits_total", "Verification cache hits");
 pub static VERIFICATION_CACHE_MISSES: Lazy<IntCounter> = counter!("verification_cache_misses_total", "Verification cache misses");
+pub static RESPONSE_CACHE_HITS: Lazy<IntCounter> = counter!("response_cache_hits_total", "HTTP response cache hits");
+pub static RESPONSE_CACHE_MISSES: Lazy<IntCounter> = counter!("response_cache_misses_total", "HTTP response cache misses");
 
 // ── Resources ────────────────────────────────────────────────────────────────────
 pub static RESOURCE_RECORDINGS: Lazy<IntCounter> =
@@ -276,12 +292,23 @@ pub fn register_all(r: &Registry) -> prometheus::Result<()> {
     r.register(Box::new(CACHE_HITS.clone()))?;
     r.register(Box::new(CACHE_MISSES.clone()))?;
     r.register(Box::new(CACHE_EVICTIONS.clone()))?;
+    r.register(Box::new(CACHE_LOADER_ERRORS.clone()))?;
+    r.register(Box::new(CACHE_REJECTED_OVERSIZE.clone()))?;
+    r.register(Box::new(CACHE_PUT_SKIPPED_FROZEN.clone()))?;
+    r.register(Box::new(CACHE_STALE_SERVED.clone()))?;
+    r.register(Box::new(CACHE_CHECKSUM_FAILURES.clone()))?;
+    r.register(Box::new(CACHE_TIMEOUTS.clone()))?;
     r.register(Box::new(CACHE_SIZE_BYTES.clone()))?;
     r.register(Box::new(CACHE_ENTRIES.clone()))?;
+    r.register(Box::new(CACHE_HIT_RATIO.clone()))?;
+    r.register(Box::new(CACHE_AVG_CACHED_LATENCY_MICROS.clone()))?;
+    r.register(Box::new(CACHE_AVG_UNCACHED_LATENCY_MICROS.clone()))?;
     r.register(Box::new(ABI_CACHE_HITS.clone()))?;
     r.register(Box::new(ABI_CACHE_MISSES.clone()))?;
     r.register(Box::new(VERIFICATION_CACHE_HITS.clone()))?;
     r.register(Box::new(VERIFICATION_CACHE_MISSES.clone()))?;
+    r.register(Box::new(RESPONSE_CACHE_HITS.clone()))?;
+    r.register(Box::new(RESPONSE_CACHE_MISSES.clone()))?;
     r.register(Box::new(RESOURCE_RECORDINGS.clone()))?;
     r.register(Box::new(RESOURCE_ALERTS_FIRED.clone()))?;
     r.register(Box::new(RESOURCE_FORECAST_RUNS.clone()))?;
@@ -1,7 +1,10 @@
 use axum::extract::State;
 use axum::http::{header, StatusCode};
 use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
 
+use crate::cache::CacheMetricsSnapshot;
 use crate::metrics;
 use crate::state::AppState;
 
@@ -17,6 +20,26 @@ pub async fn metrics_endpoint(State(state): State<AppState>) -> impl IntoRespons
     )
 }
 
+/// Body of `GET /cache/stats`
+#[derive(Debug, Serialize)]
+pub struct CacheStatsResponse {
+    /// Includes the backend name and eviction policy
+    #[serde(flatten)]
+    pub metrics: CacheMetricsSnapshot,
+    pub entry_count: u64,
+    pub estimated_size_bytes: u64,
+}
+
+/// GET /cache/stats — read-only JSON view of the cache counters
+pub async fn cache_stats_endpoint(State(state): State<AppState>) -> Json<CacheStatsResponse> {
+    state.cache.run_pending().await;
+    Json(CacheStatsResponse {
+        metrics: state.cache.metrics().snapshot(),
+        entry_count: state.cache.entry_count(),
+        estimated_size_bytes: state.cache.estimated_size_bytes(),
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -77,4 +100,31 @@ mod tests {
         assert!(text.contains("contracts_published_total"));
         assert!(text.contains("# TYPE"));
     }
+
+    #[tokio::test]
+    async fn test_cache_stats_endpoint() {
+        let state = test_state();
+        state.cache.put("c1", "k1", "v1".to_string(), None).await;
+        state.cache.get("c1", "k1").await;
+        state.cache.get("c1", "k1").await;
+        state.cache.get("c1", "missing").await;
+        state.cache.state_cache.run_pending_tasks().await;
+
+        let resp = cache_stats_endpoint(State(state)).await.into_response();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(stats["hits"], 2);
+        assert_eq!(stats["misses"], 1);
+        assert_eq!(stats["evictions"], 0);
+        assert_eq!(stats["entry_count"], 1);
+        assert_eq!(stats["backend"], "moka");
+        assert_eq!(stats["eviction_policy"], "tiny-lfu");
+        let hit_rate = stats["hit_rate"].as_f64().unwrap();
+        assert!((hit_rate - 2.0 / 3.0).abs() < 1e-9);
+        assert!(stats["avg_cached_latency_us"].is_number());
+    }
 }
@@ -1,10 +1,11 @@
 use axum::{
-    routing::{get, patch, post},
+    middleware,
+    routing::{delete, get, patch, post},
     Router,
 };
 
 use crate::{
-    breaking_changes, compatibility_testing_handlers, custom_metrics_handlers,
+    auth_middleware, breaking_changes, cache_handlers, compatibility_testing_handlers, custom_metrics_handlers,
     deprecation_handlers, handlers, metrics_handler, migration_handlers, state::AppState,
 };
 
@@ -173,6 +174,26 @@ pub fn health_routes() -> Router<AppState> {
         .route("/api/stats", get(handlers::get_stats))
 }
 
+/// Cache administration; every route requires a bearer token, and the routes that
+/// write or drop entries require an admin one
+pub fn cache_routes() -> Router<AppState> {
+    let admin = Router::new()
+        .route("/cache/prime", post(cache_handlers::prime_cache))
+        .route(
+            "/cache/:contract_id",
+            delete(cache_handlers::invalidate_contract_cache),
+        )
+        .route(
+            "/cache/:contract_id/:key",
+            delete(cache_handlers::invalidate_cache_key),
+        )
+        .route_layer(middleware::from_fn(auth_middleware::admin_middleware));
+    Router::new()
+        .route("/cache/stats", get(metrics_handler::cache_stats_endpoint))
+        .merge(admin)
+        .route_layer(middleware::from_fn(auth_middleware::auth_middleware))
+}
+
 pub fn migration_routes() -> Router<AppState> {
     Router::new()
         // Database Migration Versioning and Rollback (Issue #252)
@@ -18,10 +18,14 @@ pub struct AppState {
 impl AppState {
     pub fn new(db: PgPool, registry: Registry, is_shutting_down: Arc<AtomicBool>) -> Self {
         let config = CacheConfig::from_env();
+        let cache = CacheLayer::try_new(config).unwrap_or_else(|e| {
+            tracing::error!("{}; falling back to default cache config", e);
+            CacheLayer::new(CacheConfig::default())
+        });
         Self {
             db,
             started_at: Instant::now(),
-            cache: Arc::new(CacheLayer::new(config)),
+            cache: Arc::new(cache),
             registry,
             is_shutting_down,
         }